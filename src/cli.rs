@@ -0,0 +1,1132 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::rc::Rc;
+use clap::{App, Arg, ArgMatches};
+use serde_json::{json, Value};
+
+use crate::diff::{diff_profiles, print_diff, DRIFT_EXIT_CODE};
+use crate::encoding::{Decoder, Encoding, EncodingLog, SharedLog};
+use crate::input::{collect_inputs, parse_separator, skip_lines, InputLines, InputSource};
+use crate::json_stream::{stream_json_records, JsonRecords};
+use crate::layout::Layout;
+use crate::mask_definition::MaskDefinition;
+use crate::parquet_input::stream_parquet_rows;
+use crate::report::{ordered_columns, print_report, RunInfo};
+use crate::rules::{print_rule_results, RuleSet, RULES_EXIT_CODE};
+use crate::snapshot::{merge_snapshots, Snapshot};
+use crate::sniff::sniff;
+use crate::{DepthOverflow, MaskRegistry, ParseError, Profile, Profiler, ProfilerOptions};
+
+// Appends the lines rejected by the last batch to the quarantine file.
+fn write_quarantine(writer: &mut BufWriter<File>, profile: &mut Profile) {
+    for line in profile.rejected.drain(..) {
+        if let Err(e) = writeln!(writer, "{}", line) {
+            eprintln!("Error: unable to write quarantine file: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn init_control_character_descriptions() -> HashMap<char, &'static str> {
+    let mut ref_map = HashMap::new();
+    ref_map.insert('\u{0000}', "NUL - Null char");
+    ref_map.insert('\u{0001}', "SOH - Start of Heading");
+    ref_map.insert('\u{0002}', "STX - Start of Text");
+    ref_map.insert('\u{0003}', "ETX - End of Text");
+    ref_map.insert('\u{0004}', "EOT - End of Transmission");
+    ref_map.insert('\u{0005}', "ENQ - Enquiry");
+    ref_map.insert('\u{0006}', "ACK - Acknowledgment");
+    ref_map.insert('\u{0007}', "BEL - Bell");
+    ref_map.insert('\u{0008}', "BS - Back Space");
+    ref_map.insert('\u{0009}', "HT - Horizontal Tab");
+    ref_map.insert('\u{000A}', "LF - Line Feed");
+    ref_map.insert('\u{000B}', "VT - Vertical Tab");
+    ref_map.insert('\u{000C}', "FF - Form Feed");
+    ref_map.insert('\u{000D}', "CR - Carriage Return");
+    ref_map.insert('\u{000E}', "SO - Shift Out / X-On");
+    ref_map.insert('\u{000F}', "SI - Shift In / X-Off");
+    ref_map.insert('\u{0010}', "DLE - Data Line Escape");
+    ref_map.insert('\u{0011}', "DC1 - Device Control 1 (oft. XON)");
+    ref_map.insert('\u{0012}', "DC2 - Device Control 2");
+    ref_map.insert('\u{0013}', "DC3 - Device Control 3 (oft. XOFF)");
+    ref_map.insert('\u{0014}', "DC4 - Device Control 4");
+    ref_map.insert('\u{0015}', "NAK - Negative Acknowledgement");
+    ref_map.insert('\u{0016}', "SYN - Synchronous Idle");
+    ref_map.insert('\u{0017}', "ETB - End of Transmit Block");
+    ref_map.insert('\u{0018}', "CAN - Cancel");
+    ref_map.insert('\u{0019}', "EM - End of Medium");
+    ref_map.insert('\u{001A}', "SUB - Substitute");
+    ref_map.insert('\u{001B}', "ESC - Escape");
+    ref_map.insert('\u{001C}', "FS - File Separator");
+    ref_map.insert('\u{001D}', "GS - Group Separator");
+    ref_map.insert('\u{001E}', "RS - Record Separator");
+    ref_map.insert('\u{001F}', "US - Unit Separator");
+    ref_map.insert('\u{008A}', "LINE TABULATION SET * Deprecated from Unicode 3.2, 2002");
+    ref_map.insert('\u{0090}', "ERROR - Undefined CTRL Character.");
+    ref_map.insert('\u{009A}', "LATIN CAPITAL S WITH CARON");
+    ref_map.insert('\u{FDD0}', "Non-character code point");
+    ref_map.insert('\u{FDD1}', "Non-character code point");
+    ref_map.insert('\u{FDD2}', "Non-character code point");
+    ref_map.insert('\u{FDD3}', "Non-character code point");
+    ref_map.insert('\u{FDD4}', "Non-character code point");
+    ref_map.insert('\u{FDD5}', "Non-character code point");
+    ref_map.insert('\u{FDD6}', "Non-character code point");
+    ref_map.insert('\u{FDD7}', "Non-character code point");
+    ref_map.insert('\u{FDD8}', "Non-character code point");
+    ref_map.insert('\u{FDD9}', "Non-character code point");
+    ref_map.insert('\u{FDDA}', "Non-character code point");
+    ref_map.insert('\u{FDDB}', "Non-character code point");
+    ref_map.insert('\u{FDDC}', "Non-character code point");
+    ref_map.insert('\u{FDDD}', "Non-character code point");
+    ref_map.insert('\u{FDDE}', "Non-character code point");
+    ref_map.insert('\u{FDDF}', "Non-character code point");
+    ref_map.insert('\u{FDE0}', "Non-character code point");
+    ref_map.insert('\u{FDE1}', "Non-character code point");
+    ref_map.insert('\u{FDE2}', "Non-character code point");
+    ref_map.insert('\u{FDE3}', "Non-character code point");
+    ref_map.insert('\u{FDE4}', "Non-character code point");
+    ref_map.insert('\u{FDE5}', "Non-character code point");
+    ref_map.insert('\u{FDE6}', "Non-character code point");
+    ref_map.insert('\u{FDE7}', "Non-character code point");
+    ref_map.insert('\u{FDE8}', "Non-character code point");
+    ref_map.insert('\u{FDE9}', "Non-character code point");
+    ref_map.insert('\u{FDEA}', "Non-character code point");
+    ref_map.insert('\u{FDEB}', "Non-character code point");
+    ref_map.insert('\u{FDEC}', "Non-character code point");
+    ref_map.insert('\u{FDED}', "Non-character code point");
+    ref_map.insert('\u{FDEE}', "Non-character code point");
+    ref_map.insert('\u{FDEF}', "Non-character code point");
+    ref_map.insert('\u{FFFA}', "Undefined Control Character");
+    ref_map.insert('\u{FFFB}', "Undefined Control Character");
+    ref_map.insert('\u{FFFC}', "Undefined Control Character");
+    ref_map.insert('\u{1FFFE}', "Undefined Control Character");
+    ref_map.insert('\u{1FFFF}', "Undefined Control Character");
+    ref_map.insert('\u{2FFFE}', "Undefined Control Character");
+    ref_map.insert('\u{2FFFF}', "Undefined Control Character");
+    ref_map.insert('\u{3FFFE}', "Undefined Control Character");
+    ref_map.insert('\u{3FFFF}', "Undefined Control Character");
+    ref_map.insert('\u{4FFFE}', "Undefined Control Character");
+    ref_map.insert('\u{4FFFF}', "Undefined Control Character");
+    ref_map.insert('\u{5FFFE}', "Undefined Control Character");
+    ref_map.insert('\u{5FFFF}', "Undefined Control Character");
+    ref_map.insert('\u{6FFFE}', "Undefined Control Character");
+    ref_map.insert('\u{6FFFF}', "Undefined Control Character");
+    ref_map.insert('\u{7FFFE}', "Undefined Control Character");
+    ref_map.insert('\u{7FFFF}', "Undefined Control Character");
+    ref_map.insert('\u{8FFFE}', "Undefined Control Character");
+    ref_map.insert('\u{8FFFF}', "Undefined Control Character");
+    ref_map.insert('\u{9FFFE}', "Undefined Control Character");
+    ref_map.insert('\u{9FFFF}', "Undefined Control Character");
+    ref_map.insert('\u{AFFFE}', "Undefined Control Character");
+    ref_map.insert('\u{AFFFF}', "Undefined Control Character");
+    ref_map.insert('\u{BFFFE}', "Undefined Control Character");
+    ref_map.insert('\u{BFFFF}', "Undefined Control Character");
+    ref_map.insert('\u{CFFFE}', "Undefined Control Character");
+    ref_map.insert('\u{CFFFF}', "Undefined Control Character");
+    ref_map.insert('\u{DFFFE}', "Undefined Control Character");
+    ref_map.insert('\u{DFFFF}', "Undefined Control Character");
+    ref_map.insert('\u{EFFFE}', "Undefined Control Character");
+    ref_map.insert('\u{EFFFF}', "Undefined Control Character");
+    ref_map.insert('\u{FFFFE}', "Undefined Control Character");
+    ref_map.insert('\u{FFFFF}', "Undefined Control Character");
+    ref_map.insert('\u{10FFFE}', "Undefined Control Character");
+    ref_map.insert('\u{10FFFF}', "Undefined Control Character");
+
+    ref_map
+}
+
+struct LineReader<R: Read> {
+    inner: R,
+    buf: Vec<u8>,
+}
+
+impl<R: BufRead> LineReader<R> {
+    fn new(inner: R) -> Self {
+        Self { inner, buf: Vec::new() }
+    }
+
+    fn read_line_self(&mut self) -> io::Result<Option<String>> {
+        let mut line = Vec::new();
+        let bytes_read = self.inner.read_until(b'\n', &mut line)?;
+
+        if bytes_read == 0 {
+            if !self.buf.is_empty() {
+                let cloned_buf = self.buf.clone(); 
+                self.buf.clear();
+                let cloned_string = String::from_utf8_lossy(&cloned_buf);
+                return Ok(Some(cloned_string.into_owned()));
+            }
+            return Ok(None);
+        }
+
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+
+        self.buf.extend(line.iter());
+
+        let cloned_buf = self.buf.clone();
+        self.buf.clear();
+        let cloned_string = String::from_utf8_lossy(&cloned_buf);
+        Ok(Some(cloned_string.into_owned()))
+    }
+}
+
+impl<R: Read> Read for LineReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<R: BufRead> BufRead for LineReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(&self.buf)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.buf.drain(..amt);
+    }
+
+    fn read_line(&mut self, buf: &mut String) -> io::Result<usize> {
+        buf.clear();
+        match self.read_line_self() {
+            Ok(Some(line)) => {
+                buf.push_str(&line);
+                Ok(line.len())
+            }
+            Ok(None) => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+
+// Opens an input and decodes it to UTF-8, returning the log of the byte sequences that could
+// not be decoded. Parquet is binary and is passed through untouched.
+fn open_decoded(source: &InputSource, encoding: Encoding) -> io::Result<(Box<dyn BufRead>, SharedLog)> {
+    let mut reader = source.open()?;
+    if reader.fill_buf()?.starts_with(b"PAR1") {
+        return Ok((reader, Rc::new(RefCell::new(EncodingLog::default()))));
+    }
+    let (decoder, log) = Decoder::new(reader, encoding, source.to_string());
+    Ok((Box::new(decoder), log))
+}
+
+// Counts the raw bytes passing through a reader, before they are decoded.
+struct ByteCounter<R: Read> {
+    inner: R,
+    counts: Rc<RefCell<[usize; 256]>>,
+}
+
+impl<R: Read> Read for ByteCounter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.inner.read(buf)?;
+        let mut counts = self.counts.borrow_mut();
+        for byte in &buf[..bytes_read] {
+            counts[*byte as usize] += 1;
+        }
+        Ok(bytes_read)
+    }
+}
+
+// The Unicode name of a character, or a description of the control characters that have none.
+fn character_name(c: char, control_characters: &HashMap<char, &'static str>) -> String {
+    unicode_names2::name(c).map_or_else(
+        || control_characters.get(&c).map_or("UNKNOWN".to_string(), |desc| desc.to_string()),
+        |name| name.to_string(),
+    )
+}
+
+// Reports the characters found in each column or JSON path of a profile built with
+// count_characters set, so that odd characters can be traced to the fields holding them.
+fn print_field_character_report(profile: &Profile, output_format: &str, column_order: &str) {
+    let control_characters = init_control_character_descriptions();
+    let columns = ordered_columns(profile, column_order);
+    let sorted_chars = |idx: usize| {
+        let mut chars = profile.char_maps[idx].iter().map(|(c, count)| (*c, *count)).collect::<Vec<(char, usize)>>();
+        chars.sort_unstable_by_key(|&(c, _)| c as u32);
+        chars
+    };
+
+    if output_format == "json" {
+        let report = json!({
+            "record_count": profile.record_count,
+            "columns": columns
+                .iter()
+                .map(|&idx| json!({
+                    "index": idx,
+                    "name": profile.column_names[idx],
+                    "characters": sorted_chars(idx)
+                        .iter()
+                        .map(|(c, count)| json!({
+                            "char": c.to_string(),
+                            "code_point": format!("U+{:04X}", *c as u32),
+                            "count": count,
+                            "name": character_name(*c, &control_characters),
+                        }))
+                        .collect::<Vec<Value>>(),
+                }))
+                .collect::<Vec<Value>>(),
+        });
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        return;
+    }
+
+    // one line per field first, naming the characters outside printable ASCII
+    println!("FieldCharacterSets:");
+    for &idx in &columns {
+        let chars = sorted_chars(idx);
+        let unusual = chars
+            .iter()
+            .filter(|(c, _)| !(' '..='~').contains(c))
+            .map(|(c, _)| format!("U+{:04X}", *c as u32))
+            .collect::<Vec<String>>();
+        println!(
+            "col_{:05}_{}\t{} distinct\t{}",
+            idx,
+            profile.column_names[idx],
+            chars.len(),
+            if unusual.is_empty() { "ASCII only".to_string() } else { format!("other: {}", unusual.join(" ")) },
+        );
+    }
+    println!();
+
+    println!("{:<32}\t{:<8}\t{:<8}\tdescription\tname", "column", "char", "count");
+    println!("{:-<32}\t{:-<8}\t{:-<8}\t{:-<15}\t{:-<15}", "", "", "", "", "");
+    for &idx in &columns {
+        for (c, count) in sorted_chars(idx) {
+            println!(
+                "col_{:05}_{}\t{:<8}\t{:<8}\t{}\t{}",
+                idx,
+                profile.column_names[idx],
+                c.escape_unicode(),
+                count,
+                c.escape_debug(),
+                character_name(c, &control_characters),
+            );
+        }
+    }
+}
+
+fn character_profiling(sources: &[InputSource], encoding: Encoding, count_bytes: bool) -> Result<(), std::io::Error> {
+    let ascii_control_characters = init_control_character_descriptions();
+    let mut frequency_map: HashMap<char, usize> = HashMap::new();
+    let byte_counts = Rc::new(RefCell::new([0usize; 256]));
+    let mut encoding_errors = Profile::new();
+
+    for source in sources {
+        let mut raw: Box<dyn BufRead> = source.open()?;
+        if count_bytes {
+            raw = Box::new(BufReader::new(ByteCounter { inner: raw, counts: Rc::clone(&byte_counts) }));
+        }
+        let (decoder, log) = Decoder::new(raw, encoding, source.to_string());
+
+        let mut reader = LineReader::new(decoder);
+
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 0 {
+            for c in line.chars() {
+                let count = frequency_map.entry(c).or_insert(0);
+                *count += 1;
+            }
+            line.clear();
+        }
+        let log = log.take();
+        encoding_errors.add_encoding_errors(log.count, log.sample);
+    }
+
+    if encoding_errors.encoding_error_count > 0 {
+        println!("Encoding errors: {} (counted below as U+FFFD)", encoding_errors.encoding_error_count);
+        for error in &encoding_errors.encoding_errors {
+            println!("{}: line {} (byte {}): {}", error.file, error.line, error.offset, error.message);
+        }
+        println!();
+    }
+
+    println!("{:<8}\t{:<8}\tdescription\tname", "char", "count");
+    println!("{:-<8}\t{:-<8}\t{:-<15}\t{:-<15}", "", "", "", "");
+
+    let mut sorted_chars: Vec<(char, usize)> = frequency_map.into_iter().collect();
+    sorted_chars.sort_unstable_by_key(|&(c, _)| c as u32);
+
+    for (c, count) in sorted_chars {
+        let character_name = character_name(c, &ascii_control_characters);
+        println!("{:<8}\t{:<8}\t{}\t{}", c.escape_unicode(), count, c.escape_debug(), character_name);
+    }
+
+    if count_bytes {
+        // raw byte values as read, before decoding; bytes above 0x7f are parts of multi-byte
+        // sequences or characters of a legacy encoding
+        println!();
+        println!("{:<8}\t{:<8}\tdescription", "byte", "count");
+        println!("{:-<8}\t{:-<8}\t{:-<15}", "", "", "");
+        for (byte, count) in byte_counts.borrow().iter().enumerate().filter(|(_, count)| **count > 0) {
+            let description = if byte < 0x80 {
+                character_name(byte as u8 as char, &ascii_control_characters)
+            } else {
+                "non-ASCII byte".to_string()
+            };
+            println!("0x{:02x}    \t{:<8}\t{}", byte, count, description);
+        }
+    }
+    Ok(())
+}
+
+// Parses a share of a column, for --threshold.
+fn parse_share(text: &str) -> Result<f64, String> {
+    match text.parse::<f64>() {
+        Ok(share) if (0.0..=1.0).contains(&share) => Ok(share),
+        _ => Err(format!("'{}' is not a number between 0.0 and 1.0", text)),
+    }
+}
+
+// Parses a count that must be at least 1, for --batch-size.
+fn parse_positive(text: &str) -> Result<usize, String> {
+    match text.parse::<usize>() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(format!("'{}' is not a whole number of at least 1", text)),
+    }
+}
+
+fn load_snapshot(path: &str) -> (Profile, RunInfo) {
+    match Snapshot::read(&InputSource::File(path.into())) {
+        Ok(snapshot) => snapshot.into_profile(),
+        Err(e) => {
+            eprintln!("Error: unable to read snapshot {}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Prints the drift between two profiles and exits non-zero if it exceeds the threshold.
+// Only the masks of the first grain of multi-grain profiles are compared.
+fn report_drift(baseline: &(Profile, RunInfo), current: &(Profile, RunInfo), threshold: f64, output_format: &str) {
+    if baseline.1.first_grain() != current.1.first_grain() {
+        eprintln!(
+            "Error: cannot compare profiles with different grains ('{}' and '{}')",
+            baseline.1.first_grain(),
+            current.1.first_grain()
+        );
+        std::process::exit(1);
+    }
+    let findings = diff_profiles(&baseline.0, &current.0, threshold);
+    print_diff(&findings, threshold, output_format);
+    if findings.iter().any(|finding| finding.significant) {
+        std::process::exit(DRIFT_EXIT_CODE);
+    }
+}
+
+// Writes the optional snapshot, then reports the profile: as drift against a baseline
+// snapshot, as data quality assertion results, or as the profiling report itself.
+fn finish_profile(profile: Profile, info: RunInfo, matches: &ArgMatches) {
+    let output_format = matches.value_of("output_format").unwrap();
+
+    if let Some(path) = matches.value_of("snapshot") {
+        if let Err(e) = Snapshot::from_profile(&profile, &info).write(path) {
+            eprintln!("Error: unable to write snapshot {}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(path) = matches.value_of("diff_against") {
+        let threshold = *matches.get_one::<f64>("threshold").unwrap();
+        let baseline = load_snapshot(path);
+        report_drift(&baseline, &(profile, info), threshold, output_format);
+    } else if let Some(path) = matches.value_of("rules") {
+        let rules = match RuleSet::load(path) {
+            Ok(rules) => rules,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        };
+        let results = rules.evaluate(&profile);
+        print_rule_results(&results, output_format);
+        if results.iter().any(|result| !result.passed) {
+            std::process::exit(RULES_EXIT_CODE);
+        }
+    } else {
+        print_report(&profile, &info, output_format, matches.value_of("sort_columns").unwrap());
+    }
+}
+
+// The bytefreq-rs command line tool. Grains are looked up in `masks`, so a program that
+// registers its own masks before calling run can select them with -g.
+pub fn run(mut masks: MaskRegistry) {
+
+    let matches = App::new("Bytefreq Data Profiler")
+        .version("1.0")
+        .author("Andrew Morgan <andrew.morgan@6point6.co.uk>\nEdward Jones <edward.jones@6point6.co.uk>\n")
+        .about("A command-line tool to generate data profiling reports based on various masking strategies.")
+        .arg(
+            Arg::new("grain")
+	        .short('g')
+  	        .long("grain")
+	        .value_name("GRAIN")
+                .help("Sets the grain type for masking:\n\
+                   'H' - High grain (A for uppercase letters, a for lowercase letters, 9 for digits)\n\
+                   'L' - Low grain (repeated pattern characters will be compressed to one)\n\
+                   'U' - Unicode (uses Unicode general categories for masking\n\
+                   'LU'- Low grain Unicode (repeated pattern classes compressed to one)\n\
+                   Other names select masks registered by programs embedding the bytefreq library.\n\
+                   A comma separated list (e.g. 'LU,H') profiles several grains in one pass, and adds\n\
+                   a drill-down of the finer grain's masks under the coarser grain's")
+	        .takes_value(true)
+	        .default_value("LU"),
+        )
+        .arg(
+            Arg::new("delimiter")
+                .short('d')
+                .long("delimiter")
+                .value_name("DELIMITER")
+                .help("Sets the delimiter used to separate fields in input tabular data. It may be several\n\
+                   characters long (e.g. '||' or '~|~'), use the escapes \\t, \\xHH, \\0 and \\\\ or a control\n\
+                   picture such as '␞', or be one of the names tab, comma, pipe, semicolon, space, ctrl-a,\n\
+                   unit-separator and record-separator.\n\
+                   Default: 'auto' - guessed from the first lines of the input ('|' if nothing fits)")
+                .takes_value(true)
+                .default_value("auto"),
+        )
+        .arg(
+            Arg::new("format")
+                .short('f')
+                .long("format")
+                .value_name("FORMAT")
+                .help("Sets the format of the input data:\n\
+                   'auto' - Guessed from the first lines of the input (default)\n\
+                   'json' - JSON data (each line should contain a JSON object)\n\
+                   'tabular' - Tabular data (first line should be the header)\n\
+                   'csv' - RFC 4180 CSV data (quoted fields may contain delimiters and newlines)\n\
+                   'parquet' - Apache Parquet files (nested columns are profiled as JSON paths)\n\
+                   'fixed' - Fixed-width records cut into fields by the --layout file")
+                .takes_value(true)
+                .default_value("auto"),
+        )
+        .arg(
+	    Arg::new("report")
+		.short('r')
+		.long("report")
+		.value_name("REPORT")
+		.help("Sets the type of report to generate:\n\
+		       'DQ' - Data Quality (default)\n\
+		       'CP' - Character Profiling")
+		.takes_value(true)
+		.default_value("DQ"),
+	)
+        .arg(
+            Arg::new("pathdepth")
+                .short('p')
+                .long("pathdepth")
+                .value_name("PATHDEPTH")
+                .help("Sets the depth for JSON paths (applicable for JSON data only).\n\
+                   The depth is the number of object keys in a path, array indexes do not count.")
+                .takes_value(true)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("2"),
+        )
+        .arg(
+            Arg::new("depth_overflow")
+                .long("depth-overflow")
+                .value_name("MODE")
+                .help("Sets how JSON objects nested deeper than the path depth are profiled:\n\
+                   'collapse' - The object is serialized and masked as a whole at the truncated path\n\
+                   'placeholder' - Nested values are counted under the truncated path plus '...'")
+                .takes_value(true)
+                .possible_values(["collapse", "placeholder"])
+                .default_value("collapse"),
+        )
+        .arg(
+            Arg::new("remove_array_numbers")
+                .short('a')
+                .long("remove-array-numbers")
+                .value_name("REMOVE_ARRAY_NUMBERS")
+                .help("Remove array numbers when set to true")
+                .takes_value(true)
+                .default_value("false"),
+        )
+        .arg(
+            Arg::new("output_format")
+                .short('o')
+                .long("output-format")
+                .value_name("OUTPUT_FORMAT")
+                .help("Sets the output format of the DQ report:\n\
+                   'text' - Tab separated text report (default)\n\
+                   'json' - Structured JSON document for downstream tooling")
+                .takes_value(true)
+                .possible_values(["text", "json"])
+                .default_value("text")
+                .global(true),
+        )
+        .arg(
+            Arg::new("sort_columns")
+                .long("sort-columns")
+                .value_name("ORDER")
+                .help("Sets the order of columns in the DQ report:\n\
+                   'header' - Header / first-seen order (default)\n\
+                   'name' - Alphabetical by column name\n\
+                   'masks' - Most distinct masks first")
+                .takes_value(true)
+                .possible_values(["header", "name", "masks"])
+                .default_value("header")
+                .global(true),
+        )
+        .arg(
+            Arg::new("semantic")
+                .long("semantic")
+                .help("Adds the semantic type of each column to the DQ report: UK postcode, email, ISO date,\n\
+                   UK phone, UUID, IPv4, IPv6, URL, currency amount, NINO or IBAN, with the share of\n\
+                   values that conform to it and the masks that do not"),
+        )
+        .arg(
+            Arg::new("snapshot")
+                .short('s')
+                .long("snapshot")
+                .value_name("SNAPSHOT")
+                .help("Also writes the profile to this JSON snapshot file, for use with the merge command")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::new("diff_against")
+                .long("diff-against")
+                .value_name("SNAPSHOT")
+                .help("Compares the profiled input against this snapshot and reports drift instead of the profile")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::new("rules")
+                .long("rules")
+                .value_name("RULES")
+                .help("Evaluates the data quality assertions in this TOML file and reports pass/fail\n\
+                   instead of the profile, exiting with status 4 when any rule fails")
+                .takes_value(true)
+                .conflicts_with("diff_against")
+                .global(true),
+        )
+        .arg(
+            Arg::new("threshold")
+                .long("threshold")
+                .value_name("THRESHOLD")
+                .help("Share (0.0 - 1.0) of a column's values a mask must gain or lose to count as drift")
+                .takes_value(true)
+                .value_parser(parse_share)
+                .default_value("0.05")
+                .global(true),
+        )
+        .arg(
+            Arg::new("json_records")
+                .long("json-records")
+                .value_name("MODE")
+                .help("Sets how records are read from JSON input:\n\
+                   'lines' - JSON Lines, one record per line (default)\n\
+                   'documents' - Concatenated or pretty-printed documents, one record each\n\
+                   'array' - The elements of a top-level array (or of the array at --json-pointer)")
+                .takes_value(true)
+                .possible_values(["lines", "documents", "array"])
+                .default_value("lines"),
+        )
+        .arg(
+            Arg::new("encoding")
+                .short('e')
+                .long("encoding")
+                .value_name("ENCODING")
+                .help("Sets the character encoding the input is decoded from before profiling:\n\
+                   'utf-8' (default), 'latin1', 'windows-1252', 'utf-16' (byte order from the BOM),\n\
+                   'utf-16le', 'utf-16be' or 'ebcdic' (code page 037). Byte sequences that are invalid\n\
+                   in the encoding are counted, reported with their offsets and profiled as U+FFFD")
+                .takes_value(true)
+                .default_value("utf-8"),
+        )
+        .arg(
+            Arg::new("bytes")
+                .long("bytes")
+                .help("With the CP report, also counts the raw byte values (0x00 - 0xFF) of the input"),
+        )
+        .arg(
+            Arg::new("by_field")
+                .long("by-field")
+                .help("With the CP report, reads the input with the -f and -d settings and counts the\n\
+                   characters of each column or JSON path separately"),
+        )
+        .arg(
+            Arg::new("record_terminator")
+                .long("record-terminator")
+                .value_name("TERMINATOR")
+                .help("Sets the text that ends each record of line based input, e.g. '\\x1e'. Takes the same\n\
+                   names and escapes as --delimiter. Default: newline ('\\r\\n' is also accepted)")
+                .takes_value(true)
+                .default_value("newline"),
+        )
+        .arg(
+            Arg::new("no_header")
+                .long("no-header")
+                .help("Tabular and csv input has no header row: columns are named col_1, col_2, ...\n\
+                   (or by --columns) and the first row is profiled as data"),
+        )
+        .arg(
+            Arg::new("columns")
+                .long("columns")
+                .value_name("NAMES")
+                .help("Comma separated column names (e.g. 'id,name,postcode') used instead of the header row")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("skip_rows")
+                .long("skip-rows")
+                .value_name("N")
+                .help("Number of preamble lines to skip at the start of each line based input (before the header)")
+                .takes_value(true)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("0"),
+        )
+        .arg(
+            Arg::new("mask_file")
+                .long("mask-file")
+                .value_name("FILE")
+                .help("TOML file defining a custom mask that maps Unicode categories, scripts, ranges or\n\
+                   characters to symbols; it is selected with -g by its name (may be repeated)")
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            Arg::new("layout")
+                .long("layout")
+                .value_name("LAYOUT")
+                .help("TOML file giving the name, start position and length of each field of fixed-width\n\
+                   records, with optional record type codes for files mixing several layouts")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("json_pointer")
+                .long("json-pointer")
+                .value_name("POINTER")
+                .help("JSON pointer (e.g. '/features') to the array holding the records; implies '--json-records array'")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("quarantine")
+                .long("quarantine")
+                .value_name("FILE")
+                .help("Writes JSON lines that fail to parse, and fixed-width records that do not fit the\n\
+                   layout, to this file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .value_name("THREADS")
+                .help("Number of worker threads used for profiling (0 = one per core)")
+                .takes_value(true)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("0"),
+        )
+        .arg(
+            Arg::new("batch_size")
+                .long("batch-size")
+                .value_name("BATCH_SIZE")
+                .help("Number of records each worker thread profiles at a time")
+                .takes_value(true)
+                .value_parser(parse_positive)
+                .default_value("10000"),
+        )
+        .arg(
+            Arg::new("include")
+                .long("include")
+                .value_name("PATTERN")
+                .help("Only profile input files whose path matches this glob (may be repeated)")
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            Arg::new("exclude")
+                .long("exclude")
+                .value_name("PATTERN")
+                .help("Skip input files whose path matches this glob (may be repeated)")
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            Arg::new("input")
+                .value_name("INPUT")
+                .help("Files, glob patterns or directories (walked recursively) to profile.\n\
+                   Reads stdin when omitted or given as '-'")
+                .multiple_values(true),
+        )
+        .subcommand(
+            App::new("diff")
+                .about("Reports drift between two profile snapshots, exiting with status 3 when it exceeds the threshold")
+                .arg(
+                    Arg::new("baseline")
+                        .value_name("BASELINE")
+                        .help("Snapshot of the reference profile")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("current")
+                        .value_name("CURRENT")
+                        .help("Snapshot of the profile to check")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new("merge")
+                .about("Combines profile snapshots (e.g. from partitions profiled on different machines) into one report")
+                .arg(
+                    Arg::new("snapshots")
+                        .value_name("SNAPSHOT")
+                        .help("Snapshot files, glob patterns or directories to merge")
+                        .required(true)
+                        .multiple_values(true),
+                ),
+        )
+        .get_matches();
+
+
+    // custom masks are registered beside the built-in grains
+    for path in matches.values_of("mask_file").into_iter().flatten() {
+        match MaskDefinition::load(path) {
+            Ok(definition) => masks.register(&definition.name.clone(), definition),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let report = matches.value_of("report").unwrap();
+    let output_format = matches.value_of("output_format").unwrap();
+
+    let threshold = *matches.get_one::<f64>("threshold").unwrap();
+
+    if let Some(diff_matches) = matches.subcommand_matches("diff") {
+        let baseline = load_snapshot(diff_matches.value_of("baseline").unwrap());
+        let current = load_snapshot(diff_matches.value_of("current").unwrap());
+        report_drift(&baseline, &current, threshold, output_format);
+        return;
+    }
+
+    if let Some(merge_matches) = matches.subcommand_matches("merge") {
+        let snapshot_args = merge_matches.values_of("snapshots").unwrap().collect::<Vec<&str>>();
+        if matches.is_present("semantic") {
+            eprintln!("Warning: snapshots do not keep semantic type counts, --semantic is ignored");
+        }
+        let merged = collect_inputs(&snapshot_args, &[], &[]).and_then(|sources| merge_snapshots(&sources));
+        match merged {
+            Ok((profile, info)) => finish_profile(profile, info, &matches),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let threads = *matches.get_one::<usize>("threads").unwrap();
+    if let Err(e) = rayon::ThreadPoolBuilder::new().num_threads(threads).build_global() {
+        eprintln!("Warning: unable to configure the thread pool: {}", e);
+    }
+
+    let input_args = matches.values_of("input").map_or_else(Vec::new, |values| values.collect());
+    let includes = matches.values_of("include").map_or_else(Vec::new, |values| values.collect());
+    let excludes = matches.values_of("exclude").map_or_else(Vec::new, |values| values.collect());
+    let sources = match collect_inputs(&input_args, &includes, &excludes) {
+        Ok(sources) => sources,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let encoding = match Encoding::from_name(matches.value_of("encoding").unwrap()) {
+        Ok(encoding) => encoding,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // with --by-field the CP report reads the input like the DQ report, to attribute the
+    // characters to the fields holding them
+    let by_field = matches.is_present("by_field");
+    if report == "CP" && !by_field {
+        //character_profiling();
+        match character_profiling(&sources, encoding, matches.is_present("bytes")) {
+            Ok(_) => println!("--------END OF REPORT--------"),
+            Err(e) => eprintln!("Error occurred during character profiling: {}", e),
+        }
+    } else {
+
+	    let grain = matches.value_of("grain").unwrap();
+            let format_arg = matches.value_of("format").unwrap();
+            let delimiter_arg = match matches.value_of("delimiter").unwrap() {
+                "auto" => None,
+                delimiter => Some(parse_separator(delimiter).unwrap_or_else(|e| {
+                    eprintln!("Error: invalid delimiter: {}", e);
+                    std::process::exit(1);
+                })),
+            };
+            let terminator = parse_separator(matches.value_of("record_terminator").unwrap()).unwrap_or_else(|e| {
+                eprintln!("Error: invalid record terminator: {}", e);
+                std::process::exit(1);
+            });
+            let skip_rows = *matches.get_one::<usize>("skip_rows").unwrap();
+
+            // Whatever is left to 'auto' is guessed from the first lines of the first input,
+            // whose reader (replaying the sniffed lines) is then used to profile it.
+            let mut first_reader = None;
+            let sniffed = match (format_arg, &delimiter_arg, matches.value_of("layout")) {
+                ("auto", _, None) | ("tabular", None, _) | ("csv", None, _) => {
+                    let hint = delimiter_arg.as_deref();
+                    let sniffing = open_decoded(&sources[0], encoding).and_then(|(reader, log)| {
+                        sniff(reader, skip_rows, &terminator, hint).map(|(sniffed, reader)| (sniffed, reader, log))
+                    });
+                    match sniffing {
+                        Ok((sniffed, reader, log)) => {
+                            first_reader = Some((reader, log));
+                            Some(sniffed)
+                        }
+                        Err(e) => {
+                            eprintln!("Warning: unable to sniff the format of {}: {}", sources[0], e);
+                            None
+                        }
+                    }
+                }
+                _ => None,
+            };
+            let format = match (format_arg, &sniffed) {
+                ("auto", _) if matches.is_present("layout") => "fixed",
+                ("auto", Some(sniffed)) => sniffed.format,
+                ("auto", None) => "tabular",
+                (format, _) => format,
+            };
+            // undetected delimiters default to a comma for csv and to a pipe otherwise
+            let default_delimiter = if format == "csv" { "," } else { "|" };
+            let delimiter = match (delimiter_arg, &sniffed) {
+                (None, Some(sniffed)) => sniffed.delimiter.clone().unwrap_or_else(|| default_delimiter.to_string()),
+                (None, None) => default_delimiter.to_string(),
+                (Some(delimiter), _) => delimiter,
+            };
+            let delimiter = delimiter.as_str();
+
+            let depth_overflow = match matches.value_of("depth_overflow").unwrap() {
+                "placeholder" => DepthOverflow::Placeholder,
+                _ => DepthOverflow::Collapse,
+            };
+            // JSON Lines are read line by line below, everything else is streamed
+            let json_records_arg = match (matches.occurrences_of("json_records"), &sniffed) {
+                (0, Some(sniffed)) if format_arg == "auto" => sniffed.json_records.unwrap_or("lines"),
+                _ => matches.value_of("json_records").unwrap(),
+            };
+            let json_records = match (json_records_arg, matches.value_of("json_pointer")) {
+                ("lines", None) => None,
+                ("documents", None) => Some(JsonRecords::Documents),
+                (_, pointer) => match JsonRecords::pointer(pointer.unwrap_or("")) {
+                    Ok(records) => Some(records),
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    }
+                },
+            };
+            let mut quarantine = matches.value_of("quarantine").map(|path| match File::create(path) {
+                Ok(file) => BufWriter::new(file),
+                Err(e) => {
+                    eprintln!("Error: unable to create quarantine file {}: {}", path, e);
+                    std::process::exit(1);
+                }
+            });
+            let layout = match (format, matches.value_of("layout")) {
+                ("fixed", Some(path)) => match Layout::load(path) {
+                    Ok(layout) => Some(layout),
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    }
+                },
+                ("fixed", None) => {
+                    eprintln!("Error: the fixed format requires a --layout file");
+                    std::process::exit(1);
+                }
+                _ => None,
+            };
+            // an explicit format means the input has a header unless told otherwise
+            let has_header = !matches.is_present("no_header")
+                && (format_arg != "auto" || sniffed.as_ref().is_none_or(|sniffed| sniffed.has_header));
+            let columns = matches.value_of("columns").map(|names| names.split(',').map(|name| name.to_string()).collect());
+            let chunk_size = *matches.get_one::<usize>("batch_size").unwrap();
+            // records are read serially in batches large enough to keep every thread busy
+            let batch_len = chunk_size * rayon::current_num_threads();
+
+            let options = ProfilerOptions {
+                grain: grain.to_string(),
+                masks,
+                format: format.to_string(),
+                delimiter: delimiter.to_string(),
+                pathdepth: *matches.get_one::<usize>("pathdepth").unwrap(),
+                remove_array_numbers: matches.value_of("remove_array_numbers").unwrap() != "false",
+                depth_overflow,
+                has_header,
+                columns,
+                layout,
+                keep_rejected: quarantine.is_some(),
+                count_characters: by_field,
+                semantic: matches.is_present("semantic"),
+                chunk_size,
+            };
+            let mut profiler = match Profiler::new(options) {
+                Ok(profiler) => profiler,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            };
+
+            for source in &sources {
+                let (reader, encoding_log) = match first_reader.take().map_or_else(|| open_decoded(source, encoding), Ok) {
+                    Ok(opened) => opened,
+                    Err(e) => {
+                        eprintln!("Warning: unable to open {}: {}", source, e);
+                        continue;
+                    }
+                };
+                let rows_before = profiler.profile().record_count;
+                profiler.start_input(&source.to_string());
+
+	    if format == "parquet" || (format == "json" && json_records.is_some()) {
+                // Parquet rows and streamed JSON records are both profiled as JSON values
+                let mut batch: Vec<Value> = Vec::with_capacity(batch_len);
+                let mut on_record = |record| {
+                    batch.push(record);
+                    if batch.len() == batch_len {
+                        profiler.feed_records(&batch);
+                        batch.clear();
+                    }
+                };
+                // (line or row, byte offset, message) of the error that ended the input early
+                let error = match &json_records {
+                    Some(records) if format == "json" => stream_json_records(reader, records, &mut on_record)
+                        .err()
+                        .map(|e| (e.line, e.offset, e.message)),
+                    _ => stream_parquet_rows(source, reader, &mut on_record)
+                        .err()
+                        .map(|e| (e.row, 0, e.message)),
+                };
+                profiler.feed_records(&batch);
+
+                // neither stream can be resynchronised after an error, so the rest of the file is skipped
+                if let Some((line, offset, message)) = error {
+                    profiler.profile_mut().add_parse_error(ParseError {
+                        file: source.to_string(),
+                        line,
+                        offset,
+                        message: format!("{} (rest of input skipped)", message),
+                    });
+                }
+            } else if format == "csv" {
+                // RFC 4180 parsing: quoted fields may contain the delimiter, escaped quotes and newlines
+                let delimiter_byte = match delimiter.as_bytes() {
+                    [byte] => *byte,
+                    _ => {
+                        eprintln!("Error: the csv format requires a single byte delimiter, got '{}'", delimiter.escape_debug());
+                        std::process::exit(1);
+                    }
+                };
+                let csv_terminator = match terminator.as_bytes() {
+                    b"\n" => csv::Terminator::CRLF,
+                    [byte] => csv::Terminator::Any(*byte),
+                    _ => {
+                        eprintln!("Error: the csv format requires a single byte record terminator, got '{}'", terminator.escape_debug());
+                        std::process::exit(1);
+                    }
+                };
+                // the preamble is skipped as plain lines, it need not be valid csv
+                let mut reader = reader;
+                let skipped_bytes = match skip_lines(&mut reader, skip_rows, terminator.as_bytes()) {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        eprintln!("Warning: unable to read {}: {}", source, e);
+                        continue;
+                    }
+                };
+                let mut csv_reader = csv::ReaderBuilder::new()
+                    .has_headers(false)
+                    .flexible(true)
+                    .delimiter(delimiter_byte)
+                    .terminator(csv_terminator)
+                    .from_reader(reader);
+
+                let mut records = csv_reader.records();
+                loop {
+                    let mut batch = Vec::with_capacity(batch_len);
+                    for result in records.by_ref() {
+                        match result {
+                            Ok(record) => batch.push(record),
+                            Err(e) => {
+                                let position = e.position();
+                                let profile = profiler.profile_mut();
+                                profile.record_count += 1;
+                                profile.add_parse_error(ParseError {
+                                    file: source.to_string(),
+                                    line: position.map_or(0, |p| p.line() as usize + skip_rows),
+                                    offset: position.map_or(0, |p| p.byte() + skipped_bytes),
+                                    message: e.to_string(),
+                                });
+                            }
+                        }
+                        if batch.len() == batch_len {
+                            break;
+                        }
+                    }
+                    if batch.is_empty() {
+                        break;
+                    }
+                    profiler.feed_csv_records(&batch);
+                }
+            } else {
+                let mut lines = InputLines::new(reader, &terminator).skip(skip_rows).filter(|line| !line.text.is_empty());
+                loop {
+                    let batch = lines.by_ref().take(batch_len).collect::<Vec<_>>();
+                    if batch.is_empty() {
+                        break;
+                    }
+                    profiler.feed_lines(&batch);
+                    if let Some(writer) = quarantine.as_mut() {
+                        write_quarantine(writer, profiler.profile_mut());
+                    }
+                }
+            }
+
+                let profile = profiler.profile_mut();
+                let log = encoding_log.take();
+                profile.add_encoding_errors(log.count, log.sample);
+                let rows = profile.record_count - rows_before;
+                profile.input_files.push((source.to_string(), rows));
+            }
+
+            if let Some(mut writer) = quarantine {
+                if let Err(e) = writer.flush() {
+                    eprintln!("Error: unable to write quarantine file: {}", e);
+                }
+            }
+
+            let profile = profiler.finish();
+            let info = RunInfo {
+                grain: grain.to_string(),
+                delimiter: delimiter.to_string(),
+                format: format.to_string(),
+                detected: sniffed.map(|sniffed| sniffed.describe(format, delimiter, has_header)),
+            };
+            if by_field {
+                print_field_character_report(&profile, output_format, matches.value_of("sort_columns").unwrap());
+                if output_format == "text" {
+                    println!("--------END OF REPORT--------");
+                }
+            } else {
+                finish_profile(profile, info, &matches);
+            }
+    }
+} // end of run
@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde_json::{json, Value};
+
+use crate::profile::Profile;
+
+// Exit status used when the drift between two profiles exceeds the threshold.
+pub const DRIFT_EXIT_CODE: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DriftKind {
+    NewColumn,
+    MissingColumn,
+    NewMask,
+    VanishedMask,
+    FrequencyShift,
+    FieldsPerLine,
+}
+
+impl fmt::Display for DriftKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            DriftKind::NewColumn => "new_column",
+            DriftKind::MissingColumn => "missing_column",
+            DriftKind::NewMask => "new_mask",
+            DriftKind::VanishedMask => "vanished_mask",
+            DriftKind::FrequencyShift => "frequency_shift",
+            DriftKind::FieldsPerLine => "fields_per_line",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// A single difference between the baseline and current profile. Shares are the fraction
+// of the column's values (or of the rows, for FieldsPerLine) taken by the mask.
+pub struct DriftFinding {
+    pub column: String,
+    pub kind: DriftKind,
+    pub mask: Option<String>,
+    pub baseline_share: f64,
+    pub current_share: f64,
+    pub significant: bool,
+}
+
+fn shares<K: Clone + Eq + std::hash::Hash>(counts: &HashMap<K, usize>) -> HashMap<K, f64> {
+    let total = counts.values().sum::<usize>().max(1) as f64;
+    counts.iter().map(|(key, count)| (key.clone(), *count as f64 / total)).collect()
+}
+
+fn compare_shares<K: Clone + Eq + std::hash::Hash + Ord>(
+    baseline: &HashMap<K, usize>,
+    current: &HashMap<K, usize>,
+    threshold: f64,
+    mut emit: impl FnMut(&K, DriftKind, f64, f64, bool),
+) {
+    let baseline_shares = shares(baseline);
+    let current_shares = shares(current);
+
+    let mut keys = baseline_shares.keys().chain(current_shares.keys()).cloned().collect::<Vec<K>>();
+    keys.sort_unstable();
+    keys.dedup();
+
+    for key in &keys {
+        match (baseline_shares.get(key), current_shares.get(key)) {
+            (None, Some(current)) => emit(key, DriftKind::NewMask, 0.0, *current, *current > threshold),
+            (Some(baseline), None) => emit(key, DriftKind::VanishedMask, *baseline, 0.0, *baseline > threshold),
+            (Some(baseline), Some(current)) if (current - baseline).abs() > threshold => {
+                emit(key, DriftKind::FrequencyShift, *baseline, *current, true)
+            }
+            _ => {}
+        }
+    }
+}
+
+// Compares two profiles column by column. Columns are matched by name, and a finding is
+// significant when the share it represents (or the change in share) exceeds the threshold.
+// New and missing columns are always significant.
+pub fn diff_profiles(baseline: &Profile, current: &Profile, threshold: f64) -> Vec<DriftFinding> {
+    let mut findings = Vec::new();
+
+    compare_shares(&baseline.field_count_map, &current.field_count_map, threshold, |fields, _, b, c, significant| {
+        findings.push(DriftFinding {
+            column: "FieldsPerLine".to_string(),
+            kind: DriftKind::FieldsPerLine,
+            mask: Some(format!("{} fields", fields)),
+            baseline_share: b,
+            current_share: c,
+            significant,
+        });
+    });
+
+    for (idx, name) in current.column_names.iter().enumerate() {
+        match baseline.column_index(name) {
+            None => findings.push(DriftFinding {
+                column: name.clone(),
+                kind: DriftKind::NewColumn,
+                mask: None,
+                baseline_share: 0.0,
+                current_share: 1.0,
+                significant: true,
+            }),
+            Some(baseline_idx) => compare_shares(
+                &baseline.frequency_maps[baseline_idx],
+                &current.frequency_maps[idx],
+                threshold,
+                |mask, kind, b, c, significant| {
+                    findings.push(DriftFinding {
+                        column: name.clone(),
+                        kind,
+                        mask: Some(mask.clone()),
+                        baseline_share: b,
+                        current_share: c,
+                        significant,
+                    });
+                },
+            ),
+        }
+    }
+
+    for name in &baseline.column_names {
+        if current.column_index(name).is_none() {
+            findings.push(DriftFinding {
+                column: name.clone(),
+                kind: DriftKind::MissingColumn,
+                mask: None,
+                baseline_share: 1.0,
+                current_share: 0.0,
+                significant: true,
+            });
+        }
+    }
+
+    findings
+}
+
+pub fn print_diff(findings: &[DriftFinding], threshold: f64, output_format: &str) {
+    let drift_count = findings.iter().filter(|finding| finding.significant).count();
+
+    if output_format == "json" {
+        let report = json!({
+            "threshold": threshold,
+            "drift_detected": drift_count > 0,
+            "significant_findings": drift_count,
+            "findings": findings
+                .iter()
+                .map(|finding| json!({
+                    "column": finding.column,
+                    "change": finding.kind.to_string(),
+                    "mask": finding.mask,
+                    "baseline_share": finding.baseline_share,
+                    "current_share": finding.current_share,
+                    "significant": finding.significant,
+                }))
+                .collect::<Vec<Value>>(),
+        });
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        return;
+    }
+
+    println!();
+    println!("Profile Drift Report (threshold {})", threshold);
+    println!("Significant findings: {}", drift_count);
+    println!();
+    println!(
+        "{:<32}\t{:<16}\t{:<8}\t{:<8}\t{:<8}\tpattern",
+        "column", "change", "baseline", "current", "drift"
+    );
+    println!("{:-<32}\t{:-<16}\t{:-<8}\t{:-<8}\t{:-<8}\t{:-<8}", "", "", "", "", "", "");
+    for finding in findings {
+        println!(
+            "{:<32}\t{:<16}\t{:<8.4}\t{:<8.4}\t{:<8}\t{}",
+            finding.column,
+            finding.kind.to_string(),
+            finding.baseline_share,
+            finding.current_share,
+            if finding.significant { "YES" } else { "no" },
+            finding.mask.as_deref().unwrap_or("")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profiler::{Profiler, ProfilerOptions};
+
+    fn profile(lines: &[&str]) -> Profile {
+        let mut profiler = Profiler::new(ProfilerOptions::default()).unwrap();
+        for line in lines {
+            profiler.feed_line(line).unwrap();
+        }
+        profiler.finish()
+    }
+
+    fn finding<'a>(findings: &'a [DriftFinding], column: &str, kind: DriftKind) -> &'a DriftFinding {
+        findings
+            .iter()
+            .find(|finding| finding.column == column && finding.kind == kind)
+            .unwrap_or_else(|| panic!("no {} finding for {}", kind, column))
+    }
+
+    #[test]
+    fn new_and_missing_columns_are_always_significant() {
+        let baseline = profile(&["id|name", "1|Alice", "2|Bob"]);
+        let current = profile(&["id|email", "1|alice@example.com", "2|bob@example.com"]);
+        let findings = diff_profiles(&baseline, &current, 1.0);
+
+        let email = finding(&findings, "email", DriftKind::NewColumn);
+        assert!(email.significant);
+        assert_eq!((email.mask.as_deref(), email.baseline_share, email.current_share), (None, 0.0, 1.0));
+        assert!(finding(&findings, "name", DriftKind::MissingColumn).significant);
+        assert!(findings.iter().all(|finding| finding.column != "id"));
+    }
+
+    #[test]
+    fn new_and_vanished_masks_respect_the_threshold() {
+        let mut baseline = vec!["code"];
+        baseline.extend(["A1"; 9]);
+        baseline.push("AB");
+        let mut current = vec!["code"];
+        current.extend(["A1"; 9]);
+        current.push("12");
+        let (baseline, current) = (profile(&baseline), profile(&current));
+
+        let findings = diff_profiles(&baseline, &current, 0.05);
+        assert_eq!(findings.len(), 2);
+        let new = finding(&findings, "code", DriftKind::NewMask);
+        assert_eq!((new.mask.as_deref(), new.baseline_share, new.current_share), (Some("9"), 0.0, 0.1));
+        assert!(new.significant);
+        let vanished = finding(&findings, "code", DriftKind::VanishedMask);
+        assert_eq!((vanished.mask.as_deref(), vanished.baseline_share), (Some("A"), 0.1));
+        assert!(vanished.significant);
+
+        // still reported, but not as drift
+        let findings = diff_profiles(&baseline, &current, 0.2);
+        assert_eq!(findings.len(), 2);
+        assert!(findings.iter().all(|finding| !finding.significant));
+    }
+
+    #[test]
+    fn frequency_shifts_fire_above_the_threshold() {
+        let baseline = HashMap::from([("a", 50), ("b", 50)]);
+        let current = HashMap::from([("a", 80), ("b", 20)]);
+        let shifts = |threshold| {
+            let mut shifts = Vec::new();
+            compare_shares(&baseline, &current, threshold, |key, kind, b, c, significant| {
+                shifts.push((*key, kind, b, c, significant))
+            });
+            shifts
+        };
+
+        assert_eq!(
+            shifts(0.25),
+            [
+                ("a", DriftKind::FrequencyShift, 0.5, 0.8, true),
+                ("b", DriftKind::FrequencyShift, 0.5, 0.2, true)
+            ]
+        );
+        assert!(shifts(0.35).is_empty());
+    }
+
+    #[test]
+    fn fields_per_line_changes_are_reported() {
+        let baseline = profile(&["id|name", "1|a", "2|b", "3|c", "4|d"]);
+        let current = profile(&["id|name", "1|a", "2|b", "3|c", "4|d|extra"]);
+        let findings = diff_profiles(&baseline, &current, 0.1);
+
+        let fields = findings.iter().filter(|finding| finding.kind == DriftKind::FieldsPerLine).collect::<Vec<_>>();
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[0].mask.as_deref(), Some("2 fields"));
+        assert_eq!((fields[0].baseline_share, fields[0].current_share), (1.0, 0.75));
+        assert!(fields[0].significant);
+        assert_eq!(fields[1].mask.as_deref(), Some("3 fields"));
+        assert_eq!((fields[1].kind, fields[1].current_share, fields[1].significant), (DriftKind::FieldsPerLine, 0.25, true));
+        assert!(fields.iter().all(|finding| finding.column == "FieldsPerLine"));
+    }
+}
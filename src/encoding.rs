@@ -0,0 +1,419 @@
+use std::cell::RefCell;
+use std::io::{self, BufRead, Read};
+use std::rc::Rc;
+
+use crate::profile::{ParseError, PARSE_ERROR_SAMPLE};
+
+// The character encoding of an input, which is decoded to UTF-8 before it is profiled.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Latin1,
+    Windows1252,
+    // UTF-16 with the byte order taken from a byte order mark, little endian without one
+    Utf16,
+    Utf16Le,
+    Utf16Be,
+    // EBCDIC code page 037 (US / Canada)
+    Ebcdic037,
+}
+
+impl Encoding {
+    pub fn from_name(name: &str) -> Result<Encoding, String> {
+        match name.to_ascii_lowercase().replace('_', "-").as_str() {
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "latin1" | "latin-1" | "iso-8859-1" => Ok(Encoding::Latin1),
+            "windows-1252" | "cp1252" => Ok(Encoding::Windows1252),
+            "utf-16" | "utf16" => Ok(Encoding::Utf16),
+            "utf-16le" | "utf16le" => Ok(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" => Ok(Encoding::Utf16Be),
+            "ebcdic" | "cp037" | "ibm037" => Ok(Encoding::Ebcdic037),
+            _ => Err(format!("unknown encoding '{}'", name)),
+        }
+    }
+}
+
+const CP037: [u16; 256] = [
+    0x0000, 0x0001, 0x0002, 0x0003, 0x009c, 0x0009, 0x0086, 0x007f,
+    0x0097, 0x008d, 0x008e, 0x000b, 0x000c, 0x000d, 0x000e, 0x000f,
+    0x0010, 0x0011, 0x0012, 0x0013, 0x009d, 0x0085, 0x0008, 0x0087,
+    0x0018, 0x0019, 0x0092, 0x008f, 0x001c, 0x001d, 0x001e, 0x001f,
+    0x0080, 0x0081, 0x0082, 0x0083, 0x0084, 0x000a, 0x0017, 0x001b,
+    0x0088, 0x0089, 0x008a, 0x008b, 0x008c, 0x0005, 0x0006, 0x0007,
+    0x0090, 0x0091, 0x0016, 0x0093, 0x0094, 0x0095, 0x0096, 0x0004,
+    0x0098, 0x0099, 0x009a, 0x009b, 0x0014, 0x0015, 0x009e, 0x001a,
+    0x0020, 0x00a0, 0x00e2, 0x00e4, 0x00e0, 0x00e1, 0x00e3, 0x00e5,
+    0x00e7, 0x00f1, 0x00a2, 0x002e, 0x003c, 0x0028, 0x002b, 0x007c,
+    0x0026, 0x00e9, 0x00ea, 0x00eb, 0x00e8, 0x00ed, 0x00ee, 0x00ef,
+    0x00ec, 0x00df, 0x0021, 0x0024, 0x002a, 0x0029, 0x003b, 0x00ac,
+    0x002d, 0x002f, 0x00c2, 0x00c4, 0x00c0, 0x00c1, 0x00c3, 0x00c5,
+    0x00c7, 0x00d1, 0x00a6, 0x002c, 0x0025, 0x005f, 0x003e, 0x003f,
+    0x00f8, 0x00c9, 0x00ca, 0x00cb, 0x00c8, 0x00cd, 0x00ce, 0x00cf,
+    0x00cc, 0x0060, 0x003a, 0x0023, 0x0040, 0x0027, 0x003d, 0x0022,
+    0x00d8, 0x0061, 0x0062, 0x0063, 0x0064, 0x0065, 0x0066, 0x0067,
+    0x0068, 0x0069, 0x00ab, 0x00bb, 0x00f0, 0x00fd, 0x00fe, 0x00b1,
+    0x00b0, 0x006a, 0x006b, 0x006c, 0x006d, 0x006e, 0x006f, 0x0070,
+    0x0071, 0x0072, 0x00aa, 0x00ba, 0x00e6, 0x00b8, 0x00c6, 0x00a4,
+    0x00b5, 0x007e, 0x0073, 0x0074, 0x0075, 0x0076, 0x0077, 0x0078,
+    0x0079, 0x007a, 0x00a1, 0x00bf, 0x00d0, 0x00dd, 0x00de, 0x00ae,
+    0x005e, 0x00a3, 0x00a5, 0x00b7, 0x00a9, 0x00a7, 0x00b6, 0x00bc,
+    0x00bd, 0x00be, 0x005b, 0x005d, 0x00af, 0x00a8, 0x00b4, 0x00d7,
+    0x007b, 0x0041, 0x0042, 0x0043, 0x0044, 0x0045, 0x0046, 0x0047,
+    0x0048, 0x0049, 0x00ad, 0x00f4, 0x00f6, 0x00f2, 0x00f3, 0x00f5,
+    0x007d, 0x004a, 0x004b, 0x004c, 0x004d, 0x004e, 0x004f, 0x0050,
+    0x0051, 0x0052, 0x00b9, 0x00fb, 0x00fc, 0x00f9, 0x00fa, 0x00ff,
+    0x005c, 0x00f7, 0x0053, 0x0054, 0x0055, 0x0056, 0x0057, 0x0058,
+    0x0059, 0x005a, 0x00b2, 0x00d4, 0x00d6, 0x00d2, 0x00d3, 0x00d5,
+    0x0030, 0x0031, 0x0032, 0x0033, 0x0034, 0x0035, 0x0036, 0x0037,
+    0x0038, 0x0039, 0x00b3, 0x00db, 0x00dc, 0x00d9, 0x00da, 0x009f,
+];
+
+// Windows-1252 characters for 0x80 - 0x9f; the five unassigned bytes decode to the C1
+// control characters of the same value, as in the WHATWG encoding standard.
+const WINDOWS_1252_HIGH: [u16; 32] = [
+    0x20ac, 0x0081, 0x201a, 0x0192, 0x201e, 0x2026, 0x2020, 0x2021,
+    0x02c6, 0x2030, 0x0160, 0x2039, 0x0152, 0x008d, 0x017d, 0x008f,
+    0x0090, 0x2018, 0x2019, 0x201c, 0x201d, 0x2022, 0x2013, 0x2014,
+    0x02dc, 0x2122, 0x0161, 0x203a, 0x0153, 0x009d, 0x017e, 0x0178,
+];
+
+// Byte sequences that could not be decoded, shared between a Decoder and its owner.
+#[derive(Default)]
+pub struct EncodingLog {
+    pub count: usize,
+    // the first errors in input order
+    pub sample: Vec<ParseError>,
+}
+
+pub type SharedLog = Rc<RefCell<EncodingLog>>;
+
+// Decodes its input to UTF-8 as it is read (as a BufRead, so no further buffering is
+// needed). Byte sequences that are invalid in the encoding
+// are replaced with U+FFFD and recorded, with their byte offset and line, in the log.
+pub struct Decoder<R: BufRead> {
+    inner: R,
+    encoding: Encoding,
+    file: String,
+    // raw bytes read but not decoded yet, such as the start of a split multi-byte sequence
+    pending: Vec<u8>,
+    // decoded bytes waiting to be read
+    decoded: Vec<u8>,
+    decoded_pos: usize,
+    // bytes at the front of the inner reader's buffer already known to be valid UTF-8
+    valid_ahead: usize,
+    // byte offset of the start of `pending`, and the line it is on
+    offset: u64,
+    line: usize,
+    log: SharedLog,
+}
+
+impl<R: BufRead> Decoder<R> {
+    pub fn new(inner: R, encoding: Encoding, file: String) -> (Self, SharedLog) {
+        let log = Rc::new(RefCell::new(EncodingLog::default()));
+        let decoder = Decoder {
+            inner,
+            encoding,
+            file,
+            pending: Vec::new(),
+            decoded: Vec::new(),
+            decoded_pos: 0,
+            valid_ahead: 0,
+            offset: 0,
+            line: 1,
+            log: Rc::clone(&log),
+        };
+        (decoder, log)
+    }
+
+    fn record_error(&mut self, position: usize, message: String) {
+        let line = self.line + self.decoded.iter().filter(|b| **b == b'\n').count();
+        let mut log = self.log.borrow_mut();
+        log.count += 1;
+        if log.sample.len() < PARSE_ERROR_SAMPLE {
+            log.sample.push(ParseError {
+                file: self.file.clone(),
+                line,
+                offset: self.offset + position as u64,
+                message,
+            });
+        }
+    }
+
+    fn push_char(&mut self, c: char) {
+        let mut buf = [0; 4];
+        self.decoded.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+    }
+
+    // Decodes as much of `pending` as possible. Returns the number of bytes decoded.
+    fn decode_pending(&mut self, at_eof: bool) -> usize {
+        let pending = std::mem::take(&mut self.pending);
+        let mut pos = 0;
+        match self.encoding {
+            Encoding::Utf8 => loop {
+                match std::str::from_utf8(&pending[pos..]) {
+                    Ok(text) => {
+                        self.decoded.extend_from_slice(text.as_bytes());
+                        pos = pending.len();
+                        break;
+                    }
+                    Err(e) => {
+                        let valid = e.valid_up_to();
+                        self.decoded.extend_from_slice(&pending[pos..pos + valid]);
+                        pos += valid;
+                        let invalid_len = match e.error_len() {
+                            Some(len) => len,
+                            None if at_eof => pending.len() - pos,
+                            // wait for the rest of a sequence split across reads
+                            None => break,
+                        };
+                        let bytes = &pending[pos..pos + invalid_len];
+                        let hex = bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<String>>().join(" ");
+                        self.record_error(pos, format!("invalid UTF-8 sequence {}", hex));
+                        self.push_char(char::REPLACEMENT_CHARACTER);
+                        pos += invalid_len;
+                    }
+                }
+            },
+            Encoding::Latin1 => {
+                for &byte in &pending {
+                    self.push_char(char::from(byte));
+                }
+                pos = pending.len();
+            }
+            Encoding::Windows1252 => {
+                for &byte in &pending {
+                    let code = match byte {
+                        0x80..=0x9f => WINDOWS_1252_HIGH[byte as usize - 0x80],
+                        _ => byte as u16,
+                    };
+                    self.push_char(char::from_u32(code as u32).unwrap());
+                }
+                pos = pending.len();
+            }
+            Encoding::Ebcdic037 => {
+                for &byte in &pending {
+                    self.push_char(char::from_u32(CP037[byte as usize] as u32).unwrap());
+                }
+                pos = pending.len();
+            }
+            Encoding::Utf16 | Encoding::Utf16Le | Encoding::Utf16Be => {
+                let big_endian = self.encoding == Encoding::Utf16Be;
+                let unit_at = |pos: usize| {
+                    let bytes = [pending[pos], pending[pos + 1]];
+                    if big_endian {
+                        u16::from_be_bytes(bytes)
+                    } else {
+                        u16::from_le_bytes(bytes)
+                    }
+                };
+                while pos + 2 <= pending.len() {
+                    let unit = unit_at(pos);
+                    match unit {
+                        0xd800..=0xdbff if pos + 4 > pending.len() && !at_eof => break,
+                        0xd800..=0xdbff if pos + 4 <= pending.len() && (0xdc00..=0xdfff).contains(&unit_at(pos + 2)) => {
+                            let low = unit_at(pos + 2);
+                            let code = 0x10000 + (((unit as u32) - 0xd800) << 10) + ((low as u32) - 0xdc00);
+                            self.push_char(char::from_u32(code).unwrap());
+                            pos += 4;
+                        }
+                        0xd800..=0xdfff => {
+                            self.record_error(pos, format!("unpaired UTF-16 surrogate {:04x}", unit));
+                            self.push_char(char::REPLACEMENT_CHARACTER);
+                            pos += 2;
+                        }
+                        _ => {
+                            self.push_char(char::from_u32(unit as u32).unwrap());
+                            pos += 2;
+                        }
+                    }
+                }
+                if at_eof && pos < pending.len() {
+                    self.record_error(pos, "odd trailing byte in UTF-16 input".to_string());
+                    self.push_char(char::REPLACEMENT_CHARACTER);
+                    pos = pending.len();
+                }
+            }
+        }
+        self.pending = pending;
+        self.pending.drain(..pos);
+        pos
+    }
+
+    // Reads and decodes until there is decoded output or the input is exhausted.
+    fn refill(&mut self) -> io::Result<()> {
+        self.decoded.clear();
+        self.decoded_pos = 0;
+        while self.decoded.is_empty() {
+            let chunk = self.inner.fill_buf()?;
+            let at_eof = chunk.is_empty();
+            if at_eof && self.pending.is_empty() {
+                return Ok(());
+            }
+            self.pending.extend_from_slice(chunk);
+            let chunk_len = chunk.len();
+            self.inner.consume(chunk_len);
+
+            let start = self.offset == 0;
+            if start && self.encoding == Encoding::Utf16 {
+                if self.pending.len() < 2 && !at_eof {
+                    continue;
+                }
+                self.encoding = if self.pending.starts_with(&[0xfe, 0xff]) {
+                    Encoding::Utf16Be
+                } else {
+                    Encoding::Utf16Le
+                };
+            }
+            let decoded_len = self.decode_pending(at_eof);
+            // a byte order mark is not part of the data
+            if start && self.decoded.starts_with("\u{feff}".as_bytes()) {
+                self.decoded.drain(..3);
+            }
+            self.line += self.decoded.iter().filter(|b| **b == b'\n').count();
+            self.offset += decoded_len as u64;
+            if at_eof {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R: BufRead> BufRead for Decoder<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.decoded_pos < self.decoded.len() {
+            return Ok(&self.decoded[self.decoded_pos..]);
+        }
+        // Valid UTF-8 is handed out straight from the inner reader's buffer, without a copy.
+        if self.valid_ahead > 0 {
+            return Ok(&self.inner.fill_buf()?[..self.valid_ahead]);
+        }
+        if self.encoding == Encoding::Utf8 && self.pending.is_empty() {
+            let chunk = self.inner.fill_buf()?;
+            let bom = self.offset == 0 && chunk.starts_with("\u{feff}".as_bytes());
+            let valid = match std::str::from_utf8(chunk) {
+                Ok(_) => chunk.len(),
+                Err(e) => e.valid_up_to(),
+            };
+            if valid > 0 && !bom {
+                self.valid_ahead = valid;
+                return Ok(&self.inner.fill_buf()?[..valid]);
+            }
+        }
+        self.refill()?;
+        Ok(&self.decoded[self.decoded_pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if self.decoded_pos < self.decoded.len() {
+            self.decoded_pos += amt;
+            return;
+        }
+        if let Ok(chunk) = self.inner.fill_buf() {
+            self.line += chunk[..amt].iter().filter(|b| **b == b'\n').count();
+        }
+        self.inner.consume(amt);
+        self.valid_ahead -= amt;
+        self.offset += amt as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+
+    // Decodes the bytes read through a buffer of every size from 1 to 8 bytes, so that
+    // multi-byte sequences are split across reads, and checks each gives the same result.
+    fn decode(bytes: &[u8], encoding: Encoding) -> (String, EncodingLog) {
+        let mut results = (1..=8).map(|capacity| {
+            let (mut decoder, log) = Decoder::new(BufReader::with_capacity(capacity, bytes), encoding, "test".to_string());
+            let mut text = String::new();
+            decoder.read_to_string(&mut text).unwrap();
+            drop(decoder);
+            (text, Rc::try_unwrap(log).ok().unwrap().into_inner())
+        });
+        let (text, log) = results.next().unwrap();
+        for (other_text, other_log) in results {
+            assert_eq!(other_text, text);
+            assert_eq!(other_log.count, log.count);
+            let positions = |log: &EncodingLog| log.sample.iter().map(|e| (e.line, e.offset)).collect::<Vec<_>>();
+            assert_eq!(positions(&other_log), positions(&log));
+        }
+        (text, log)
+    }
+
+    fn errors(log: &EncodingLog) -> Vec<(usize, u64, &str)> {
+        log.sample.iter().map(|e| (e.line, e.offset, e.message.as_str())).collect()
+    }
+
+    #[test]
+    fn utf8_sequences_split_across_reads() {
+        let text = "£5 €6 𝄞\nnext ✓";
+        let (decoded, log) = decode(text.as_bytes(), Encoding::Utf8);
+        assert_eq!(decoded, text);
+        assert_eq!(log.count, 0);
+    }
+
+    #[test]
+    fn invalid_utf8_is_replaced_and_logged() {
+        let (decoded, log) = decode(b"ab\xffcd\n\xe2\x82\nx\xe2\x82", Encoding::Utf8);
+        assert_eq!(decoded, "ab\u{fffd}cd\n\u{fffd}\nx\u{fffd}");
+        assert_eq!(
+            errors(&log),
+            [
+                (1, 2, "invalid UTF-8 sequence ff"),
+                (2, 6, "invalid UTF-8 sequence e2 82"),
+                (3, 10, "invalid UTF-8 sequence e2 82"),
+            ]
+        );
+    }
+
+    #[test]
+    fn utf8_byte_order_mark_is_dropped() {
+        assert_eq!(decode(b"\xef\xbb\xbfid|name", Encoding::Utf8).0, "id|name");
+    }
+
+    #[test]
+    fn utf16_byte_order_and_surrogate_pairs() {
+        // 'A', then U+1D11E as the surrogate pair d834 dd1e
+        assert_eq!(decode(&[0xfe, 0xff, 0x00, 0x41, 0xd8, 0x34, 0xdd, 0x1e], Encoding::Utf16).0, "A𝄞");
+        assert_eq!(decode(&[0xff, 0xfe, 0x41, 0x00, 0x34, 0xd8, 0x1e, 0xdd], Encoding::Utf16).0, "A𝄞");
+        // little endian without a byte order mark
+        assert_eq!(decode(&[0x41, 0x00, 0x34, 0xd8, 0x1e, 0xdd], Encoding::Utf16).0, "A𝄞");
+        assert_eq!(decode(&[0x00, 0x41, 0xd8, 0x34, 0xdd, 0x1e], Encoding::Utf16Be).0, "A𝄞");
+    }
+
+    #[test]
+    fn invalid_utf16_is_replaced_and_logged() {
+        let (decoded, log) = decode(&[0x00, 0xd8, 0x41, 0x00, 0x1e, 0xdd, 0x0a, 0x00, 0x42], Encoding::Utf16Le);
+        assert_eq!(decoded, "\u{fffd}A\u{fffd}\n\u{fffd}");
+        assert_eq!(
+            errors(&log),
+            [
+                (1, 0, "unpaired UTF-16 surrogate d800"),
+                (1, 4, "unpaired UTF-16 surrogate dd1e"),
+                (2, 8, "odd trailing byte in UTF-16 input"),
+            ]
+        );
+    }
+
+    #[test]
+    fn single_byte_code_pages() {
+        assert_eq!(decode(&[0x80, 0xe9], Encoding::Latin1).0, "\u{80}é");
+        assert_eq!(decode(&[0x80, 0x93, 0x94, 0x81, 0xe9], Encoding::Windows1252).0, "€“”\u{81}é");
+        let (decoded, log) = decode(b"\xc8\x85\x93\x93\x96\x40\xf1\xf2\x25", Encoding::Ebcdic037);
+        assert_eq!(decoded, "Hello 12\n");
+        assert_eq!(log.count, 0);
+    }
+}
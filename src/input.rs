@@ -0,0 +1,351 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use walkdir::WalkDir;
+
+// A single stream of input data, either stdin or a file on disk.
+pub enum InputSource {
+    Stdin,
+    File(PathBuf),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+}
+
+impl Compression {
+    // Magic bytes win; the file extension is only consulted when the header is inconclusive.
+    fn detect(header: &[u8], path: Option<&Path>) -> Compression {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else if header.starts_with(b"BZh") {
+            Compression::Bzip2
+        } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Compression::Xz
+        } else {
+            match path.and_then(|p| p.extension()).and_then(|ext| ext.to_str()) {
+                Some("gz") | Some("gzip") => Compression::Gzip,
+                Some("zst") | Some("zstd") => Compression::Zstd,
+                Some("bz2") => Compression::Bzip2,
+                Some("xz") => Compression::Xz,
+                _ => Compression::None,
+            }
+        }
+    }
+}
+
+impl InputSource {
+    // Opens the source, transparently stream-decompressing gzip, zstd, bzip2 and xz data.
+    pub fn open(&self) -> io::Result<Box<dyn BufRead>> {
+        let (mut raw, path): (Box<dyn BufRead>, Option<&Path>) = match self {
+            InputSource::Stdin => (Box::new(BufReader::new(io::stdin())), None),
+            InputSource::File(path) => (Box::new(BufReader::new(File::open(path)?)), Some(path.as_path())),
+        };
+
+        let compression = Compression::detect(raw.fill_buf()?, path);
+        let reader: Box<dyn BufRead> = match compression {
+            Compression::None => raw,
+            Compression::Gzip => Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(raw))),
+            Compression::Zstd => Box::new(BufReader::new(zstd::stream::read::Decoder::with_buffer(raw)?)),
+            Compression::Bzip2 => Box::new(BufReader::new(bzip2::bufread::MultiBzDecoder::new(raw))),
+            Compression::Xz => Box::new(BufReader::new(xz2::bufread::XzDecoder::new_multi_decoder(raw))),
+        };
+        Ok(reader)
+    }
+}
+
+impl fmt::Display for InputSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InputSource::Stdin => write!(f, "<stdin>"),
+            InputSource::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+fn build_globset(patterns: &[&str]) -> Result<Option<GlobSet>, String> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern).map_err(|e| format!("invalid pattern '{}': {}", pattern, e))?;
+        builder.add(glob);
+    }
+    builder.build().map(Some).map_err(|e| e.to_string())
+}
+
+fn is_glob_pattern(arg: &str) -> bool {
+    arg.contains(['*', '?', '['])
+}
+
+// Expands the positional input arguments into the list of sources to profile.
+// Arguments may be plain files, glob patterns or directories (walked recursively),
+// with '-' (or no arguments at all) meaning stdin. The include and exclude patterns
+// filter every file that is found, matched against its path.
+pub fn collect_inputs(args: &[&str], includes: &[&str], excludes: &[&str]) -> Result<Vec<InputSource>, String> {
+    if args.is_empty() {
+        return Ok(vec![InputSource::Stdin]);
+    }
+
+    let include_set = build_globset(includes)?;
+    let exclude_set = build_globset(excludes)?;
+    let wanted = |path: &Path| {
+        include_set.as_ref().is_none_or(|set| set.is_match(path))
+            && !exclude_set.as_ref().is_some_and(|set| set.is_match(path))
+    };
+
+    let mut sources = Vec::new();
+
+    for arg in args {
+        if *arg == "-" {
+            sources.push(InputSource::Stdin);
+            continue;
+        }
+
+        let candidates: Vec<PathBuf> = if is_glob_pattern(arg) {
+            glob::glob(arg)
+                .map_err(|e| format!("invalid glob '{}': {}", arg, e))?
+                .filter_map(Result::ok)
+                .collect()
+        } else {
+            vec![PathBuf::from(arg)]
+        };
+
+        for candidate in candidates {
+            if candidate.is_dir() {
+                for entry in WalkDir::new(&candidate).sort_by_file_name().into_iter().filter_map(Result::ok) {
+                    if entry.file_type().is_file() && wanted(entry.path()) {
+                        sources.push(InputSource::File(entry.into_path()));
+                    }
+                }
+            } else if !candidate.exists() {
+                return Err(format!("input not found: {}", candidate.display()));
+            } else if wanted(&candidate) {
+                sources.push(InputSource::File(candidate));
+            }
+        }
+    }
+
+    if sources.is_empty() {
+        return Err("no input files matched".to_string());
+    }
+    Ok(sources)
+}
+
+// Named separators accepted by -d and --record-terminator.
+const NAMED_SEPARATORS: [(&str, &str); 10] = [
+    ("tab", "\t"),
+    ("comma", ","),
+    ("pipe", "|"),
+    ("semicolon", ";"),
+    ("space", " "),
+    ("newline", "\n"),
+    ("ctrl-a", "\x01"),
+    ("soh", "\x01"),
+    ("unit-separator", "\x1f"),
+    ("record-separator", "\x1e"),
+];
+
+// Interprets a delimiter or record terminator given on the command line: a name such as
+// 'tab' or 'ctrl-a', or text with the escapes \t, \n, \r, \0, \\ and \xHH. A Unicode control
+// picture such as '␞' (U+241E) stands for the control character it depicts. Anything else,
+// including multi-character delimiters like '||' or '~|~', is taken literally.
+pub fn parse_separator(spec: &str) -> Result<String, String> {
+    if let Some((_, separator)) = NAMED_SEPARATORS.iter().find(|(name, _)| name.eq_ignore_ascii_case(spec)) {
+        return Ok(separator.to_string());
+    }
+
+    let mut separator = String::new();
+    let mut chars = spec.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('t') => separator.push('\t'),
+                Some('n') => separator.push('\n'),
+                Some('r') => separator.push('\r'),
+                Some('0') => separator.push('\0'),
+                Some('\\') => separator.push('\\'),
+                Some('x') => {
+                    let hex = chars.by_ref().take(2).collect::<String>();
+                    match u8::from_str_radix(&hex, 16) {
+                        Ok(byte) if hex.len() == 2 && byte.is_ascii() => separator.push(byte as char),
+                        _ => return Err(format!("invalid escape '\\x{}' in '{}': expected \\x00 to \\x7f", hex, spec)),
+                    }
+                }
+                Some(other) => return Err(format!("unknown escape '\\{}' in '{}'", other, spec)),
+                None => return Err(format!("'{}' ends with a lone backslash", spec)),
+            },
+            '\u{2400}'..='\u{241f}' => separator.push(char::from((c as u32 - 0x2400) as u8)),
+            '\u{2421}' => separator.push('\x7f'),
+            _ => separator.push(c),
+        }
+    }
+    if separator.is_empty() {
+        return Err("a delimiter or record terminator cannot be empty".to_string());
+    }
+    Ok(separator)
+}
+
+// Reads one record, up to and including the terminator (or to the end of the input), into
+// `buf`. Returns the number of bytes read, 0 at the end of the input.
+pub fn read_record<R: BufRead + ?Sized>(reader: &mut R, terminator: &[u8], buf: &mut Vec<u8>) -> io::Result<usize> {
+    let last = terminator[terminator.len() - 1];
+    let mut total = 0;
+    loop {
+        let bytes_read = reader.read_until(last, buf)?;
+        total += bytes_read;
+        if bytes_read == 0 || buf.ends_with(terminator) {
+            return Ok(total);
+        }
+    }
+}
+
+// Reads past the first `count` records of a reader, returning the number of bytes skipped.
+pub fn skip_lines<R: BufRead + ?Sized>(reader: &mut R, count: usize, terminator: &[u8]) -> io::Result<u64> {
+    let mut skipped = 0;
+    let mut line = Vec::new();
+    for _ in 0..count {
+        line.clear();
+        let bytes_read = read_record(reader, terminator, &mut line)?;
+        if bytes_read == 0 {
+            break;
+        }
+        skipped += bytes_read as u64;
+    }
+    Ok(skipped)
+}
+
+// A line of input together with its position in the (decompressed) stream.
+pub struct InputLine {
+    // 1-based line number
+    pub number: usize,
+    // byte offset of the start of the line
+    pub offset: u64,
+    pub text: String,
+}
+
+// Iterates over the lines (or records ending in another terminator) of a reader, tracking
+// line numbers and byte offsets. Terminators are stripped, and with the default '\n' so is
+// a preceding '\r'. Iteration stops at the first read error.
+pub struct InputLines<R: BufRead> {
+    reader: R,
+    terminator: Vec<u8>,
+    number: usize,
+    offset: u64,
+}
+
+impl<R: BufRead> InputLines<R> {
+    pub fn new(reader: R, terminator: &str) -> Self {
+        InputLines { reader, terminator: terminator.as_bytes().to_vec(), number: 0, offset: 0 }
+    }
+}
+
+impl<R: BufRead> Iterator for InputLines<R> {
+    type Item = InputLine;
+
+    fn next(&mut self) -> Option<InputLine> {
+        let mut bytes = Vec::new();
+        let result = read_record(&mut self.reader, &self.terminator, &mut bytes).and_then(|bytes_read| {
+            String::from_utf8(bytes)
+                .map(|text| (bytes_read, text))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        });
+        match result {
+            Ok((0, _)) => None,
+            Ok((bytes_read, mut text)) => {
+                self.number += 1;
+                let offset = self.offset;
+                self.offset += bytes_read as u64;
+                if text.as_bytes().ends_with(&self.terminator) {
+                    text.truncate(text.len() - self.terminator.len());
+                    if self.terminator == b"\n" && text.ends_with('\r') {
+                        text.pop();
+                    }
+                }
+                Some(InputLine { number: self.number, offset, text })
+            }
+            Err(e) => {
+                eprintln!("Warning: stopped reading after line {}: {}", self.number, e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn separators_are_parsed() {
+        for (spec, expected) in [
+            ("\\t", "\t"),
+            ("\\x01", "\x01"),
+            ("a\\\\b", "a\\b"),
+            ("tab", "\t"),
+            ("TAB", "\t"),
+            ("ctrl-a", "\x01"),
+            ("␞", "\x1e"),
+            ("␡", "\x7f"),
+            ("~|~", "~|~"),
+            ("|", "|"),
+        ] {
+            assert_eq!(parse_separator(spec).as_deref(), Ok(expected), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn bad_separators_are_rejected() {
+        assert_eq!(
+            parse_separator("\\xff"),
+            Err("invalid escape '\\xff' in '\\xff': expected \\x00 to \\x7f".to_string())
+        );
+        assert!(parse_separator("\\x1").is_err());
+        assert_eq!(parse_separator("|\\"), Err("'|\\' ends with a lone backslash".to_string()));
+        assert_eq!(parse_separator("\\q"), Err("unknown escape '\\q' in '\\q'".to_string()));
+        assert!(parse_separator("").is_err());
+    }
+
+    fn records(input: &str, terminator: &str) -> Vec<String> {
+        let mut reader = Cursor::new(input.as_bytes());
+        let mut records = Vec::new();
+        loop {
+            let mut buf = Vec::new();
+            if read_record(&mut reader, terminator.as_bytes(), &mut buf).unwrap() == 0 {
+                return records;
+            }
+            records.push(String::from_utf8(buf).unwrap());
+        }
+    }
+
+    #[test]
+    fn records_end_at_the_whole_terminator() {
+        // the last byte of the terminator alone does not end a record
+        assert_eq!(records("a|b~|~c~d~|~e", "~|~"), ["a|b~|~", "c~d~|~", "e"]);
+        assert_eq!(records("a␞b␞", "␞"), ["a␞", "b␞"]);
+        assert_eq!(records("1|a\n\x1e2|b\x1e", "\x1e"), ["1|a\n\x1e", "2|b\x1e"]);
+    }
+
+    #[test]
+    fn lines_keep_their_numbers_and_offsets() {
+        let lines = InputLines::new(Cursor::new("a|b\r\nc|d\ne".as_bytes()), "\n").collect::<Vec<InputLine>>();
+        let lines = lines.iter().map(|line| (line.number, line.offset, line.text.as_str())).collect::<Vec<_>>();
+        assert_eq!(lines, [(1, 0, "a|b"), (2, 5, "c|d"), (3, 9, "e")]);
+
+        let records = InputLines::new(Cursor::new("a~|~b\r~|~".as_bytes()), "~|~").collect::<Vec<InputLine>>();
+        let records = records.iter().map(|record| record.text.as_str()).collect::<Vec<_>>();
+        assert_eq!(records, ["a", "b\r"]);
+    }
+}
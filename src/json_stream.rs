@@ -0,0 +1,253 @@
+use std::fmt;
+use std::io::{self, Read};
+
+use serde::de::{DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde_json::{Map, Value};
+
+// How records are found in a JSON input that is not JSON Lines.
+pub enum JsonRecords {
+    // concatenated (possibly pretty-printed) documents, each document is a record
+    Documents,
+    // the elements of the array found at this JSON pointer (split into reference tokens)
+    // in each document; an empty pointer is the top-level array
+    Pointer(Vec<String>),
+}
+
+impl JsonRecords {
+    // Parses an RFC 6901 JSON pointer such as '/features' or '/ChargeDevice'.
+    pub fn pointer(pointer: &str) -> Result<JsonRecords, String> {
+        if pointer.is_empty() {
+            return Ok(JsonRecords::Pointer(Vec::new()));
+        }
+        if !pointer.starts_with('/') {
+            return Err(format!("invalid JSON pointer '{}': must start with '/'", pointer));
+        }
+        let tokens = pointer[1..]
+            .split('/')
+            .map(|token| token.replace("~1", "/").replace("~0", "~"))
+            .collect();
+        Ok(JsonRecords::Pointer(tokens))
+    }
+}
+
+// Where the JSON stream stopped being parseable.
+pub struct StreamError {
+    pub line: usize,
+    pub offset: u64,
+    pub message: String,
+}
+
+// Counts the bytes consumed by the parser. serde_json reads a Read one byte at a time,
+// so the count is exact and gives the byte offset of a parse error.
+struct CountingReader<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.inner.read(buf)?;
+        self.count += bytes_read as u64;
+        Ok(bytes_read)
+    }
+}
+
+// Streams the records of a JSON input to `on_record` one at a time, so that a huge
+// top-level array never has to be held in memory as a whole.
+pub fn stream_json_records<R: Read, F: FnMut(Value)>(
+    reader: R,
+    records: &JsonRecords,
+    mut on_record: F,
+) -> Result<(), StreamError> {
+    let mut counting = CountingReader { inner: reader, count: 0 };
+    let result = match records {
+        JsonRecords::Documents => serde_json::Deserializer::from_reader(&mut counting)
+            .into_iter::<Value>()
+            .try_for_each(|document| document.map(&mut on_record)),
+        JsonRecords::Pointer(path) => {
+            let mut deserializer = serde_json::Deserializer::from_reader(&mut counting);
+            loop {
+                // end() only succeeds once nothing but whitespace is left
+                if deserializer.end().is_ok() {
+                    break Ok(());
+                }
+                let seed = PointerSeed { path, on_record: &mut on_record };
+                if let Err(e) = seed.deserialize(&mut deserializer) {
+                    break Err(e);
+                }
+            }
+        }
+    };
+
+    result.map_err(|e| {
+        let message = e.to_string();
+        StreamError {
+            line: e.line(),
+            // the last byte consumed is the one the parser stopped at
+            offset: counting.count.saturating_sub(1),
+            message: match message.rsplit_once(" at line ") {
+                Some((message, _)) => message.to_string(),
+                None => message,
+            },
+        }
+    })
+}
+
+// Walks down the remaining pointer tokens while deserializing, skipping everything off
+// the path with IgnoredAny. At the end of the path an array's elements are handed out as
+// records one by one, and an object is handed out as a single record.
+struct PointerSeed<'a, F> {
+    path: &'a [String],
+    on_record: &'a mut F,
+}
+
+impl<'de, F: FnMut(Value)> DeserializeSeed<'de> for PointerSeed<'_, F> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, F: FnMut(Value)> Visitor<'de> for PointerSeed<'_, F> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a JSON document")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        match self.path.split_first() {
+            None => {
+                while let Some(element) = seq.next_element::<Value>()? {
+                    (self.on_record)(element);
+                }
+            }
+            Some((token, rest)) => {
+                let wanted = token.parse::<usize>().ok();
+                let mut idx = 0;
+                loop {
+                    let found = if Some(idx) == wanted {
+                        seq.next_element_seed(PointerSeed { path: rest, on_record: &mut *self.on_record })?
+                    } else {
+                        seq.next_element::<IgnoredAny>()?.map(|_| ())
+                    };
+                    if found.is_none() {
+                        break;
+                    }
+                    idx += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        match self.path.split_first() {
+            None => {
+                let mut record = Map::new();
+                while let Some((key, value)) = map.next_entry::<String, Value>()? {
+                    record.insert(key, value);
+                }
+                (self.on_record)(Value::Object(record));
+            }
+            Some((token, rest)) => {
+                while let Some(key) = map.next_key::<String>()? {
+                    if &key == token {
+                        map.next_value_seed(PointerSeed { path: rest, on_record: &mut *self.on_record })?;
+                    } else {
+                        map.next_value::<IgnoredAny>()?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // scalars are never records, whether on or off the pointer path
+    fn visit_bool<E>(self, _: bool) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_unit<E>(self) -> Result<(), E> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn stream(input: &str, records: &JsonRecords) -> (Vec<Value>, Result<(), StreamError>) {
+        let mut values = Vec::new();
+        let result = stream_json_records(input.as_bytes(), records, |value| values.push(value));
+        (values, result)
+    }
+
+    #[test]
+    fn pointer_into_a_nested_array() {
+        let input = r#"{"meta": {"count": 2, "items": [0]}, "data": {"items": [{"a": 1}, {"a": [2, 3]}], "next": null}}"#;
+        let (values, result) = stream(input, &JsonRecords::pointer("/data/items").unwrap());
+        assert!(result.is_ok());
+        assert_eq!(values, [json!({"a": 1}), json!({"a": [2, 3]})]);
+
+        // an array index picks out a single element, handed out as one record
+        let (values, _) = stream(input, &JsonRecords::pointer("/data/items/1").unwrap());
+        assert_eq!(values, [json!({"a": [2, 3]})]);
+    }
+
+    #[test]
+    fn pointer_tokens_are_unescaped() {
+        match JsonRecords::pointer("/a~1b/c~0d").unwrap() {
+            JsonRecords::Pointer(tokens) => assert_eq!(tokens, ["a/b", "c~d"]),
+            JsonRecords::Documents => panic!("expected a pointer"),
+        }
+        assert!(JsonRecords::pointer("features").is_err());
+    }
+
+    #[test]
+    fn missing_pointer_targets_give_no_records() {
+        let input = r#"{"data": {"items": [1, 2]}, "count": 3}"#;
+        for pointer in ["/missing", "/data/rows", "/count/items", "/data/items/5"] {
+            let (values, result) = stream(input, &JsonRecords::pointer(pointer).unwrap());
+            assert!(result.is_ok(), "{}", pointer);
+            assert!(values.is_empty(), "{}", pointer);
+        }
+    }
+
+    #[test]
+    fn documents_stop_at_a_parse_error() {
+        let input = "{\"a\": 1}\n{\n  \"a\": 2\n}\n{\"a\": oops}\n{\"a\": 4}\n";
+        let (values, result) = stream(input, &JsonRecords::Documents);
+        assert_eq!(values, [json!({"a": 1}), json!({"a": 2})]);
+        let error = result.err().unwrap();
+        assert_eq!(error.line, 5);
+        assert_eq!(error.offset, input.find("oops").unwrap() as u64);
+        assert_eq!(error.message, "expected value");
+    }
+
+    #[test]
+    fn pointer_documents_stop_at_a_parse_error() {
+        let input = "{\"rows\": [{\"a\": 1}]}\n{\"rows\": [{\"a\": 2}, {\"a\": 3,]}\n{\"rows\": [{\"a\": 4}]}\n";
+        let (values, result) = stream(input, &JsonRecords::pointer("/rows").unwrap());
+        assert_eq!(values, [json!({"a": 1}), json!({"a": 2})]);
+        assert_eq!(result.err().unwrap().line, 2);
+    }
+}
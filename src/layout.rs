@@ -0,0 +1,339 @@
+use std::collections::HashSet;
+use std::fs;
+
+use serde::Deserialize;
+
+// A fixed-width record layout file. Positions are 1-based and counted in characters, as in
+// a copybook. A single layout file lists its fields directly:
+//
+//   length = 28
+//
+//   [[field]]
+//   name = "Surname"
+//   start = 1
+//   length = 20
+//
+// A file mixing several record types gives the position of the record type code, and a
+// layout for each code; its columns are named 'RECORD.field':
+//
+//   record_type = { start = 1, length = 2 }
+//
+//   [[record]]
+//   code = "01"
+//   name = "HDR"
+//
+//   [[record.field]]
+//   name = "RunDate"
+//   start = 3
+//   length = 8
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Layout {
+    // strip the padding from both ends of every value before it is masked
+    #[serde(default = "default_trim")]
+    pub trim: bool,
+    pub record_type: Option<Position>,
+    // expected record length of a single layout file, the end of the last field by default
+    pub length: Option<usize>,
+    #[serde(default, rename = "field")]
+    pub fields: Vec<Field>,
+    #[serde(default, rename = "record")]
+    pub records: Vec<RecordLayout>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Position {
+    pub start: usize,
+    pub length: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Field {
+    pub name: String,
+    pub start: usize,
+    pub length: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RecordLayout {
+    pub code: String,
+    // prefix of the record's column names, the code by default
+    pub name: Option<String>,
+    pub length: Option<usize>,
+    #[serde(rename = "field")]
+    pub fields: Vec<Field>,
+    // report column name of each field, filled in when the layout is loaded
+    #[serde(skip)]
+    pub columns: Vec<String>,
+}
+
+fn default_trim() -> bool {
+    true
+}
+
+// A record cut into (column name, value) pairs. Fields lying wholly beyond the end of a
+// short record are left out.
+pub struct FixedRecord<'l, 't> {
+    pub fields: Vec<(&'l str, &'t str)>,
+    // set when the record is not as long as its layout says
+    pub length_error: Option<String>,
+}
+
+// Returns the text between the 1-based character positions start and start + length - 1.
+fn slice_chars(text: &str, start: usize, length: usize) -> &str {
+    let byte_at = |chars: usize| {
+        if text.is_ascii() {
+            chars.min(text.len())
+        } else {
+            text.char_indices().nth(chars).map_or(text.len(), |(idx, _)| idx)
+        }
+    };
+    &text[byte_at(start - 1)..byte_at(start - 1 + length)]
+}
+
+fn validate_fields(fields: &[Field], what: &str) -> Result<(), String> {
+    if fields.is_empty() {
+        return Err(format!("{} has no fields", what));
+    }
+    let mut names = HashSet::new();
+    for field in fields {
+        if field.start == 0 || field.length == 0 {
+            return Err(format!("field '{}' of {} needs a start of at least 1 and a non-zero length", field.name, what));
+        }
+        if !names.insert(&field.name) {
+            return Err(format!("field '{}' appears twice in {}", field.name, what));
+        }
+    }
+    Ok(())
+}
+
+fn layout_end(fields: &[Field]) -> usize {
+    fields.iter().map(|field| field.start + field.length - 1).max().unwrap_or(0)
+}
+
+impl Layout {
+    pub fn load(path: &str) -> Result<Layout, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("unable to read layout file {}: {}", path, e))?;
+        let mut layout: Layout = toml::from_str(&text).map_err(|e| format!("invalid layout file {}: {}", path, e))?;
+        layout.resolve().map_err(|e| format!("invalid layout file {}: {}", path, e))?;
+        Ok(layout)
+    }
+
+    // Checks the layout and turns a single layout into a one record layout, so that the
+    // rest of the code only deals with records.
+    fn resolve(&mut self) -> Result<(), String> {
+        match &self.record_type {
+            None => {
+                if !self.records.is_empty() {
+                    return Err("[[record]] layouts need a record_type position".to_string());
+                }
+                validate_fields(&self.fields, "the layout")?;
+                let columns = self.fields.iter().map(|field| field.name.clone()).collect();
+                self.records.push(RecordLayout {
+                    code: String::new(),
+                    name: None,
+                    length: self.length,
+                    fields: std::mem::take(&mut self.fields),
+                    columns,
+                });
+            }
+            Some(position) => {
+                if !self.fields.is_empty() || self.length.is_some() {
+                    return Err("a layout with a record_type describes its fields in [[record]] tables".to_string());
+                }
+                if self.records.is_empty() {
+                    return Err("record_type is set but there are no [[record]] layouts".to_string());
+                }
+                if position.start == 0 || position.length == 0 {
+                    return Err("record_type needs a start of at least 1 and a non-zero length".to_string());
+                }
+                let mut codes = HashSet::new();
+                for record in &mut self.records {
+                    if !codes.insert(record.code.clone()) {
+                        return Err(format!("record code '{}' appears twice", record.code));
+                    }
+                    validate_fields(&record.fields, &format!("record '{}'", record.code))?;
+                    let prefix = record.name.as_deref().unwrap_or(&record.code);
+                    record.columns = record.fields.iter().map(|field| format!("{}.{}", prefix, field.name)).collect();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Every column of every record layout, in layout order.
+    pub fn column_names(&self) -> impl Iterator<Item = &String> {
+        self.records.iter().flat_map(|record| record.columns.iter())
+    }
+
+    // Cuts a record into its fields, or explains why no layout applies to it.
+    pub fn split<'l, 't>(&'l self, text: &'t str) -> Result<FixedRecord<'l, 't>, String> {
+        let record = match &self.record_type {
+            None => &self.records[0],
+            Some(position) => {
+                let code = slice_chars(text, position.start, position.length);
+                self.records
+                    .iter()
+                    .find(|record| record.code == code)
+                    .ok_or_else(|| format!("no layout for record type '{}'", code))?
+            }
+        };
+
+        let record_length = text.chars().count();
+        let mut fields = Vec::with_capacity(record.fields.len());
+        for (field, column) in record.fields.iter().zip(&record.columns) {
+            if field.start > record_length {
+                continue;
+            }
+            let value = slice_chars(text, field.start, field.length);
+            fields.push((column.as_str(), if self.trim { value.trim() } else { value }));
+        }
+
+        let expected = record.length.unwrap_or_else(|| layout_end(&record.fields));
+        let length_error = (record_length != expected).then(|| {
+            format!("record length {} does not match the layout length {}", record_length, expected)
+        });
+
+        Ok(FixedRecord { fields, length_error })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(text: &str) -> Result<Layout, String> {
+        let mut layout: Layout = toml::from_str(text).map_err(|e| e.to_string())?;
+        layout.resolve()?;
+        Ok(layout)
+    }
+
+    const SINGLE: &str = r#"
+        [[field]]
+        name = "Surname"
+        start = 1
+        length = 6
+
+        [[field]]
+        name = "Age"
+        start = 7
+        length = 3
+    "#;
+
+    const RECORDS: &str = r#"
+        record_type = { start = 1, length = 2 }
+
+        [[record]]
+        code = "01"
+        name = "HDR"
+        [[record.field]]
+        name = "RunDate"
+        start = 3
+        length = 8
+
+        [[record]]
+        code = "02"
+        [[record.field]]
+        name = "Amount"
+        start = 3
+        length = 5
+    "#;
+
+    #[test]
+    fn a_single_layout_is_one_record() {
+        let layout = layout(SINGLE).unwrap();
+        assert_eq!(layout.records.len(), 1);
+        assert!(layout.fields.is_empty());
+        assert_eq!(layout.column_names().collect::<Vec<_>>(), ["Surname", "Age"]);
+
+        let record = layout.split("Smith  42").unwrap();
+        assert_eq!(record.fields, [("Surname", "Smith"), ("Age", "42")]);
+        assert_eq!(record.length_error, None);
+    }
+
+    #[test]
+    fn record_types_have_their_own_columns() {
+        let layout = layout(RECORDS).unwrap();
+        assert_eq!(layout.column_names().collect::<Vec<_>>(), ["HDR.RunDate", "02.Amount"]);
+
+        assert_eq!(layout.split("0120240229").unwrap().fields, [("HDR.RunDate", "20240229")]);
+        assert_eq!(layout.split("02 12.5").unwrap().fields, [("02.Amount", "12.5")]);
+        assert_eq!(layout.split("03abc").err(), Some("no layout for record type '03'".to_string()));
+    }
+
+    #[test]
+    fn bad_layouts_are_rejected() {
+        for (text, expected) in [
+            ("", "the layout has no fields"),
+            (
+                "[[field]]\nname = \"a\"\nstart = 0\nlength = 1",
+                "field 'a' of the layout needs a start of at least 1 and a non-zero length",
+            ),
+            (
+                "[[field]]\nname = \"a\"\nstart = 1\nlength = 0",
+                "field 'a' of the layout needs a start of at least 1 and a non-zero length",
+            ),
+            (
+                "[[field]]\nname = \"a\"\nstart = 1\nlength = 1\n[[field]]\nname = \"a\"\nstart = 2\nlength = 1",
+                "field 'a' appears twice in the layout",
+            ),
+            (
+                "[[record]]\ncode = \"01\"\n[[record.field]]\nname = \"a\"\nstart = 1\nlength = 1",
+                "[[record]] layouts need a record_type position",
+            ),
+            ("record_type = { start = 1, length = 2 }", "record_type is set but there are no [[record]] layouts"),
+            (
+                "record_type = { start = 0, length = 2 }\n[[record]]\ncode = \"01\"\n\
+                 [[record.field]]\nname = \"a\"\nstart = 3\nlength = 1",
+                "record_type needs a start of at least 1 and a non-zero length",
+            ),
+            (
+                "record_type = { start = 1, length = 2 }\n[[record]]\ncode = \"01\"\n\
+                 [[record.field]]\nname = \"a\"\nstart = 3\nlength = 1\n\
+                 [[record]]\ncode = \"01\"\n[[record.field]]\nname = \"b\"\nstart = 3\nlength = 1",
+                "record code '01' appears twice",
+            ),
+            (
+                "record_type = { start = 1, length = 2 }\n[[record]]\ncode = \"01\"\n\
+                 [[record.field]]\nname = \"a\"\nstart = 3\nlength = 1\n[[record.field]]\nname = \"a\"\nstart = 4\nlength = 1",
+                "field 'a' appears twice in record '01'",
+            ),
+        ] {
+            assert_eq!(layout(text).err().as_deref(), Some(expected), "{}", text);
+        }
+    }
+
+    #[test]
+    fn short_records_keep_the_fields_they_reach() {
+        let layout = layout(SINGLE).unwrap();
+        let record = layout.split("Smith  4").unwrap();
+        assert_eq!(record.fields, [("Surname", "Smith"), ("Age", "4")]);
+        assert_eq!(record.length_error.as_deref(), Some("record length 8 does not match the layout length 9"));
+
+        let record = layout.split("Jo").unwrap();
+        assert_eq!(record.fields, [("Surname", "Jo")]);
+        assert!(record.length_error.is_some());
+    }
+
+    #[test]
+    fn padding_is_kept_without_trim() {
+        let layout = layout(&format!("trim = false\n{}", SINGLE)).unwrap();
+        assert_eq!(layout.split("Smith  42").unwrap().fields, [("Surname", "Smith "), ("Age", " 42")]);
+    }
+
+    #[test]
+    fn positions_count_characters() {
+        assert_eq!(slice_chars("Zoë  Müller", 1, 5), "Zoë  ");
+        assert_eq!(slice_chars("Zoë  Müller", 6, 6), "Müller");
+        assert_eq!(slice_chars("Zoë", 3, 10), "ë");
+        assert_eq!(slice_chars("Zoë", 5, 2), "");
+
+        let layout = layout(SINGLE).unwrap();
+        let record = layout.split("Zoë   42").unwrap();
+        assert_eq!(record.fields, [("Surname", "Zoë"), ("Age", "42")]);
+        assert_eq!(record.length_error.as_deref(), Some("record length 8 does not match the layout length 9"));
+    }
+}
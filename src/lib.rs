@@ -0,0 +1,33 @@
+// Mask based data profiling. A Profiler is fed records (tabular rows, JSON documents,
+// Parquet rows or fixed-width records) and builds a Profile of the masks found in each
+// column, with their counts and reservoir sampled examples:
+//
+//   let mut profiler = Profiler::new(ProfilerOptions { grain: "H".to_string(), ..Default::default() })?;
+//   profiler.feed_line("id|postcode")?;
+//   profiler.feed_line("1|SW1 1AA")?;
+//   let profile = profiler.finish();
+//   assert_eq!(profile.count("postcode", "AA9 9AA"), 1);
+//
+// Values are masked by a Mask looked up by grain name in a MaskRegistry. The bytefreq-rs
+// command line tool (cli::run) is built on this library.
+
+pub mod cli;
+pub mod diff;
+pub mod encoding;
+pub mod input;
+pub mod json_stream;
+pub mod layout;
+pub mod mask;
+pub mod mask_definition;
+pub mod parquet_input;
+pub mod profile;
+pub mod profiler;
+pub mod report;
+pub mod rules;
+pub mod semantic;
+pub mod snapshot;
+pub mod sniff;
+
+pub use mask::{Mask, MaskRegistry};
+pub use profile::{ParseError, Profile};
+pub use profiler::{DepthOverflow, Profiler, ProfilerOptions};
@@ -0,0 +1,193 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use unic::ucd::GeneralCategory as Category;
+
+// this is a highgrain Mask that works for unicode data!
+fn high_grain_unicode_mask(c: char) -> char {
+    match c {
+        '0'..='9' => '9',
+        'a'..='z' => 'a',
+        'A'..='Z' => 'A',
+        c if c.is_ascii_punctuation() && (c == '"' || c == '-' || c == '.' || c == ',') => c,
+        c if c.is_whitespace() => ' ',
+        _ => {
+            let cat = Category::of(c);
+
+            match cat {
+                Category::UppercaseLetter => 'A',
+                Category::LowercaseLetter => 'a',
+                Category::TitlecaseLetter => 'A',
+                Category::OtherLetter => 'a',
+                Category::ModifierLetter => 'a',
+                Category::DecimalNumber => '9',
+                Category::LetterNumber => '9',
+                Category::OtherNumber => '9',
+                Category::SpaceSeparator => ' ',
+                Category::LineSeparator => ' ',
+                Category::ParagraphSeparator => ' ',
+                _ => '_',
+            }
+        }
+    }
+}
+
+fn high_grain_mask(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            'a'..='z' => 'a',
+            'A'..='Z' => 'A',
+            '0'..='9' => '9',
+            _ => c,
+        })
+        .collect()
+}
+
+fn low_grain_mask(value: &str) -> String {
+    compress_runs(&high_grain_mask(value))
+}
+
+// Compresses runs of the same mask character to one, and masks an empty value as '_'.
+pub fn compress_runs(mask: &str) -> String {
+    let mut output = String::new();
+    let mut last_char = None;
+
+    for c in mask.chars() {
+        if last_char != Some(c) {
+            output.push(c);
+            last_char = Some(c);
+        }
+    }
+    if output.is_empty() {
+        "_".to_string()
+    } else {
+        output
+    }
+}
+
+// A masking strategy: generalises a value into the pattern that its frequency is counted
+// under. Masks are shared by the profiling threads.
+pub trait Mask: Send + Sync {
+    fn mask(&self, value: &str) -> String;
+}
+
+// Any function from a value to its mask is a Mask.
+impl<F: Fn(&str) -> String + Send + Sync> Mask for F {
+    fn mask(&self, value: &str) -> String {
+        self(value)
+    }
+}
+
+// 'H': A for uppercase letters, a for lowercase letters, 9 for digits, everything else as is.
+pub struct HighGrain;
+
+// 'L': the high grain mask with runs of the same character compressed to one.
+pub struct LowGrain;
+
+// 'U': Unicode letters and numbers by their general category, other symbols as '_'.
+pub struct Unicode;
+
+// 'LU': the Unicode mask with runs of the same class compressed to one.
+pub struct LowGrainUnicode;
+
+impl Mask for HighGrain {
+    fn mask(&self, value: &str) -> String {
+        high_grain_mask(value)
+    }
+}
+
+impl Mask for LowGrain {
+    fn mask(&self, value: &str) -> String {
+        low_grain_mask(value)
+    }
+}
+
+impl Mask for Unicode {
+    fn mask(&self, value: &str) -> String {
+        value.chars().map(high_grain_unicode_mask).collect()
+    }
+}
+
+impl Mask for LowGrainUnicode {
+    fn mask(&self, value: &str) -> String {
+        low_grain_mask(&Unicode.mask(value))
+    }
+}
+
+// The masks that can be selected by grain name. It starts out with the built-in grains, and
+// programs embedding the profiler can register their own masks under new names (or replace
+// a built-in one).
+#[derive(Clone)]
+pub struct MaskRegistry {
+    masks: BTreeMap<String, Arc<dyn Mask>>,
+}
+
+impl Default for MaskRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MaskRegistry {
+    pub fn new() -> Self {
+        let mut registry = MaskRegistry { masks: BTreeMap::new() };
+        registry.register("H", HighGrain);
+        registry.register("L", LowGrain);
+        registry.register("U", Unicode);
+        registry.register("LU", LowGrainUnicode);
+        // the high grain Unicode mask is also known as 'HU'
+        registry.register("HU", Unicode);
+        registry
+    }
+
+    pub fn register<M: Mask + 'static>(&mut self, grain: &str, mask: M) {
+        self.masks.insert(grain.to_string(), Arc::new(mask));
+    }
+
+    pub fn get(&self, grain: &str) -> Result<Arc<dyn Mask>, String> {
+        self.masks.get(grain).cloned().ok_or_else(|| {
+            format!(
+                "unknown grain '{}', expected one of {}",
+                grain,
+                self.names().collect::<Vec<&str>>().join(", ")
+            )
+        })
+    }
+
+    // Registered grain names, in alphabetical order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.masks.keys().map(|grain| grain.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_grains() {
+        let masks = MaskRegistry::new();
+        let mask = |grain: &str, value: &str| masks.get(grain).unwrap().mask(value);
+        assert_eq!(mask("H", "SW1 1AA"), "AA9 9AA");
+        assert_eq!(mask("L", "01/01/2023"), "9/9/9");
+        assert_eq!(mask("L", ""), "_");
+        assert_eq!(mask("U", "Ölçü 12"), "Aaaa 99");
+        assert_eq!(mask("HU", "Ölçü 12"), "Aaaa 99");
+        assert_eq!(mask("LU", "Ölçü 12"), "Aa 9");
+    }
+
+    #[test]
+    fn unknown_grains_are_rejected() {
+        let error = MaskRegistry::new().get("X").err().unwrap();
+        assert_eq!(error, "unknown grain 'X', expected one of H, HU, L, LU, U");
+    }
+
+    #[test]
+    fn custom_masks_can_be_registered() {
+        let mut masks = MaskRegistry::new();
+        masks.register("LEN", |value: &str| value.chars().count().to_string());
+        assert_eq!(masks.get("LEN").unwrap().mask("abcd"), "4");
+        assert!(masks.names().any(|grain| grain == "LEN"));
+    }
+}
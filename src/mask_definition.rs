@@ -0,0 +1,265 @@
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+use unic::char::property::EnumeratedCharProperty;
+use unic::ucd::GeneralCategory as Category;
+use unicode_script::{Script, UnicodeScript};
+
+use crate::mask::{compress_runs, Mask};
+
+// A mask defined in a TOML file. Each [[map]] picks out characters by one of: a Unicode
+// general category ('Sc', 'Uppercase_Letter', or a group of them such as 'L'), a script
+// ('Greek' or 'Grek'), an inclusive range of characters (given as themselves or 'U+hhhh'),
+// or a list of literal characters. A character takes the symbol of the first map that picks
+// it out, or stays as it is when that map has no symbol:
+//
+//   name = "DT"          # grain name, the file name without its extension by default
+//   compress = true      # compress runs of the same symbol, as the low grain masks do
+//   other = "_"          # symbol of characters no map picks out, kept as they are if unset
+//
+//   [[map]]
+//   chars = "/:"
+//
+//   [[map]]
+//   category = "Sc"
+//   symbol = "$"
+//
+//   [[map]]
+//   range = ["a", "f"]
+//   symbol = "x"
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaskFile {
+    name: Option<String>,
+    #[serde(default)]
+    compress: bool,
+    other: Option<String>,
+    #[serde(default, rename = "map")]
+    maps: Vec<MapEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MapEntry {
+    category: Option<String>,
+    script: Option<String>,
+    range: Option<[String; 2]>,
+    chars: Option<String>,
+    symbol: Option<String>,
+}
+
+enum CharClass {
+    Categories(Vec<Category>),
+    Script(Script),
+    Range(char, char),
+    Chars(Vec<char>),
+}
+
+impl CharClass {
+    fn contains(&self, c: char) -> bool {
+        match self {
+            CharClass::Categories(categories) => categories.contains(&Category::of(c)),
+            CharClass::Script(script) => c.script() == *script,
+            CharClass::Range(first, last) => (*first..=*last).contains(&c),
+            CharClass::Chars(chars) => chars.contains(&c),
+        }
+    }
+}
+
+pub struct MaskDefinition {
+    pub name: String,
+    compress: bool,
+    other: Option<String>,
+    // tried in order, with the symbol to output (None keeps the character)
+    maps: Vec<(CharClass, Option<String>)>,
+}
+
+// A general category by its short or long name, or a group of them by its letter.
+fn parse_category(name: &str) -> Result<Vec<Category>, String> {
+    let group: Option<fn(&Category) -> bool> = match name {
+        "L" => Some(Category::is_letter),
+        "LC" => Some(Category::is_cased_letter),
+        "M" => Some(Category::is_mark),
+        "N" => Some(Category::is_number),
+        "P" => Some(Category::is_punctuation),
+        "S" => Some(Category::is_symbol),
+        "Z" => Some(Category::is_separator),
+        "C" => Some(Category::is_other),
+        _ => None,
+    };
+    let categories = Category::all_values()
+        .iter()
+        .filter(|category| match group {
+            Some(in_group) => in_group(category),
+            None => category.abbr_name() == name || category.long_name() == name,
+        })
+        .copied()
+        .collect::<Vec<Category>>();
+    if categories.is_empty() {
+        return Err(format!("unknown general category '{}'", name));
+    }
+    Ok(categories)
+}
+
+// A character given as itself or as a 'U+hhhh' code point.
+fn parse_char(text: &str) -> Result<char, String> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => text
+            .strip_prefix("U+")
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .and_then(char::from_u32)
+            .ok_or_else(|| format!("invalid character '{}', expected a single character or U+hhhh", text)),
+    }
+}
+
+fn parse_map(entry: MapEntry) -> Result<(CharClass, Option<String>), String> {
+    let class = match (entry.category, entry.script, entry.range, entry.chars) {
+        (Some(category), None, None, None) => CharClass::Categories(parse_category(&category)?),
+        (None, Some(script), None, None) => CharClass::Script(
+            Script::from_full_name(&script)
+                .or_else(|| Script::from_short_name(&script))
+                .ok_or_else(|| format!("unknown script '{}'", script))?,
+        ),
+        (None, None, Some([first, last]), None) => {
+            let (first, last) = (parse_char(&first)?, parse_char(&last)?);
+            if first > last {
+                return Err(format!("range {}-{} is empty", first.escape_unicode(), last.escape_unicode()));
+            }
+            CharClass::Range(first, last)
+        }
+        (None, None, None, Some(chars)) => CharClass::Chars(chars.chars().collect()),
+        _ => return Err("each [[map]] needs exactly one of category, script, range or chars".to_string()),
+    };
+    Ok((class, entry.symbol))
+}
+
+impl MaskDefinition {
+    pub fn load(path: &str) -> Result<MaskDefinition, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("unable to read mask file {}: {}", path, e))?;
+        let default_name = Path::new(path).file_stem().map_or(path.into(), |stem| stem.to_string_lossy());
+        MaskDefinition::parse(&text, &default_name).map_err(|e| format!("invalid mask file {}: {}", path, e))
+    }
+
+    fn parse(text: &str, default_name: &str) -> Result<MaskDefinition, String> {
+        let file: MaskFile = toml::from_str(text).map_err(|e| e.to_string())?;
+        Ok(MaskDefinition {
+            name: file.name.unwrap_or_else(|| default_name.to_string()),
+            compress: file.compress,
+            other: file.other,
+            maps: file.maps.into_iter().map(parse_map).collect::<Result<_, String>>()?,
+        })
+    }
+}
+
+impl Mask for MaskDefinition {
+    fn mask(&self, value: &str) -> String {
+        let mut output = String::with_capacity(value.len());
+        for c in value.chars() {
+            let symbol = match self.maps.iter().find(|(class, _)| class.contains(c)) {
+                Some((_, symbol)) => symbol.as_deref(),
+                None => self.other.as_deref(),
+            };
+            match symbol {
+                Some(symbol) => output.push_str(symbol),
+                None => output.push(c),
+            }
+        }
+        if self.compress {
+            compress_runs(&output)
+        } else {
+            output
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn definition(text: &str) -> MaskDefinition {
+        MaskDefinition::parse(text, "test").unwrap()
+    }
+
+    #[test]
+    fn first_matching_map_gives_the_symbol() {
+        let mask = definition(
+            r#"
+            other = "_"
+
+            [[map]]
+            chars = "/:"
+
+            [[map]]
+            category = "Nd"
+            symbol = "9"
+
+            [[map]]
+            category = "Sc"
+            symbol = "$"
+            "#,
+        );
+        assert_eq!(mask.name, "test");
+        assert_eq!(mask.mask("01/02/2023 12:30"), "99/99/9999_99:99");
+        assert_eq!(mask.mask("€5 £6"), "$9_$9");
+    }
+
+    #[test]
+    fn ranges_scripts_and_compression() {
+        let mask = definition(
+            r#"
+            name = "HEX"
+            compress = true
+
+            [[map]]
+            range = ["a", "f"]
+            symbol = "x"
+
+            [[map]]
+            range = ["U+0030", "U+0039"]
+            symbol = "x"
+
+            [[map]]
+            script = "Greek"
+            symbol = "G"
+            "#,
+        );
+        assert_eq!(mask.name, "HEX");
+        assert_eq!(mask.mask("0Xdeadbeef"), "xXx");
+        assert_eq!(mask.mask("αβγ-9"), "G-x");
+        assert_eq!(mask.mask(""), "_");
+    }
+
+    #[test]
+    fn category_groups_and_long_names() {
+        let mask = definition(
+            r#"
+            [[map]]
+            category = "Uppercase_Letter"
+            symbol = "A"
+
+            [[map]]
+            category = "L"
+            symbol = "a"
+            "#,
+        );
+        assert_eq!(mask.mask("Ωmega 7"), "Aaaaa 7");
+    }
+
+    #[test]
+    fn invalid_maps_are_rejected() {
+        for text in [
+            "[[map]]\ncategory = \"Xx\"",
+            "[[map]]\nscript = \"Klingon\"",
+            "[[map]]\nrange = [\"z\", \"a\"]",
+            "[[map]]\nrange = [\"ab\", \"c\"]",
+            "[[map]]\nchars = \"a\"\ncategory = \"L\"",
+            "[[map]]\nsymbol = \"a\"",
+            "[[map]]\nchars = \"a\"\nsimbol = \"b\"",
+        ] {
+            assert!(MaskDefinition::parse(text, "test").is_err(), "{}", text);
+        }
+    }
+}
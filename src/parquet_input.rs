@@ -0,0 +1,68 @@
+use std::fs::File;
+use std::io::{BufRead, Read};
+
+use bytes::Bytes;
+use parquet::file::reader::{ChunkReader, FileReader, SerializedFileReader};
+use serde_json::Value;
+
+use crate::input::InputSource;
+
+// Where a Parquet input stopped being readable.
+pub struct RowError {
+    // 1-based number of the row that failed, or 0 when the file itself could not be read
+    pub row: usize,
+    pub message: String,
+}
+
+// Streams the rows of a Parquet input to `on_record` one row group at a time. Each row is
+// rendered as a JSON object (groups as objects, lists as arrays, maps as objects keyed by
+// the map key, dates, timestamps and decimals as strings, binary as base64) so that it is
+// profiled with the same paths and masks as JSON input.
+//
+// Parquet needs random access to its footer, so files are read in place while stdin (or a
+// compressed file) is read into memory first.
+pub fn stream_parquet_rows<F: FnMut(Value)>(
+    source: &InputSource,
+    mut reader: Box<dyn BufRead>,
+    on_record: F,
+) -> Result<(), RowError> {
+    let file_error = |e: &dyn std::fmt::Display| RowError { row: 0, message: e.to_string() };
+
+    // an uncompressed file starts with the Parquet magic bytes
+    let plain_file = match source {
+        InputSource::File(path) => {
+            let mut file = File::open(path).map_err(|e| file_error(&e))?;
+            let mut magic = [0; 4];
+            let is_parquet = file.read_exact(&mut magic).is_ok() && &magic == b"PAR1";
+            is_parquet.then_some(file)
+        }
+        InputSource::Stdin => None,
+    };
+    match plain_file {
+        Some(file) => stream_rows(SerializedFileReader::new(file).map_err(|e| file_error(&e))?, on_record),
+        None => {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data).map_err(|e| file_error(&e))?;
+            stream_rows(SerializedFileReader::new(Bytes::from(data)).map_err(|e| file_error(&e))?, on_record)
+        }
+    }
+}
+
+fn stream_rows<R: ChunkReader + 'static, F: FnMut(Value)>(
+    reader: SerializedFileReader<R>,
+    mut on_record: F,
+) -> Result<(), RowError> {
+    let mut row = 0;
+    for idx in 0..reader.num_row_groups() {
+        let row_error = |row: usize, e: parquet::errors::ParquetError| RowError {
+            row,
+            message: format!("row group {}: {}", idx, e),
+        };
+        let row_group = reader.get_row_group(idx).map_err(|e| row_error(row + 1, e))?;
+        for result in row_group.get_row_iter(None).map_err(|e| row_error(row + 1, e))? {
+            row += 1;
+            on_record(result.map_err(|e| row_error(row, e))?.to_json_value());
+        }
+    }
+    Ok(())
+}
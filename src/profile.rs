@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+use crate::semantic::Classifier;
+
+// Number of parse errors kept (per profile) as examples for the report.
+pub const PARSE_ERROR_SAMPLE: usize = 20;
+
+// A record that could not be parsed, kept as an example in the report.
+#[derive(Clone)]
+pub struct ParseError {
+    pub file: String,
+    pub line: usize,
+    // byte offset of the error within the input
+    pub offset: u64,
+    pub message: String,
+}
+
+// The masks of one of the further grains of a multi-grain run (-g LU,H). Each value is
+// counted under the pair of its mask at the first grain and its mask at this grain, so that
+// the masks of either grain can be nested under those of the other.
+pub struct GrainMaps {
+    pub grain: String,
+    pub frequency_maps: Vec<HashMap<(String, String), usize>>,
+    pub example_maps: Vec<HashMap<(String, String), String>>,
+}
+
+impl GrainMaps {
+    pub fn new(grain: &str) -> Self {
+        GrainMaps { grain: grain.to_string(), frequency_maps: Vec::new(), example_maps: Vec::new() }
+    }
+}
+
+// Counts a value under its key, keeping a reservoir sample of one example per key.
+fn add_sampled<K: Hash + Eq + Clone>(
+    rng: &mut StdRng,
+    frequency_map: &mut HashMap<K, usize>,
+    example_map: &mut HashMap<K, String>,
+    key: K,
+    value: &str,
+) {
+    let count = frequency_map.entry(key.clone()).or_insert(0);
+    *count += 1;
+
+    // Reservoir sampling
+    if rng.gen::<f64>() < 1.0 / (*count as f64) {
+        example_map.insert(key, value.to_string());
+    }
+}
+
+// Folds the counts and examples of another profile's column into this one's. For keys seen
+// by both sides the kept example is taken from the other side with probability
+// other_count / (self_count + other_count), which preserves the uniform sampling of the
+// reservoir over the combined records.
+fn merge_sampled<K: Hash + Eq + Clone>(
+    rng: &mut StdRng,
+    frequency_map: &mut HashMap<K, usize>,
+    example_map: &mut HashMap<K, String>,
+    other_frequency_map: HashMap<K, usize>,
+    mut other_example_map: HashMap<K, String>,
+) {
+    for (key, other_count) in other_frequency_map {
+        let other_example = other_example_map.remove(&key);
+        let count = frequency_map.entry(key.clone()).or_insert(0);
+        *count += other_count;
+        let total = *count;
+
+        if let Some(example) = other_example {
+            if rng.gen::<f64>() < other_count as f64 / total as f64 {
+                example_map.insert(key, example);
+            }
+        }
+    }
+}
+
+// The accumulated state of a profiling run: the mask frequencies and reservoir sampled
+// examples of every column, plus the FieldsPerLine histogram. Each worker thread builds
+// its own Profile over a batch of records, and the results are merged together.
+pub struct Profile {
+    pub column_names: Vec<String>,
+    column_index: HashMap<String, usize>,
+    pub frequency_maps: Vec<HashMap<String, usize>>,
+    pub example_maps: Vec<HashMap<String, String>>,
+    // masks of the grains after the first in a multi-grain run
+    pub grains: Vec<GrainMaps>,
+    // histogram of the JSON value types seen at each path, empty for tabular columns
+    pub type_maps: Vec<HashMap<String, usize>>,
+    // characters of every value in each column, only counted when count_characters is set
+    pub char_maps: Vec<HashMap<char, usize>>,
+    pub count_characters: bool,
+    // number of values of each mask that conform to each semantic type, only counted when
+    // a classifier is set
+    pub semantic_maps: Vec<HashMap<String, HashMap<&'static str, usize>>>,
+    pub classifier: Option<Arc<Classifier>>,
+    pub field_count_map: HashMap<usize, usize>,
+    pub record_count: usize,
+    // records that failed to parse, and a sample of the first failures in input order
+    pub parse_error_count: usize,
+    pub parse_errors: Vec<ParseError>,
+    // byte sequences that are invalid in the input's encoding, and a sample of the first ones
+    pub encoding_error_count: usize,
+    pub encoding_errors: Vec<ParseError>,
+    // raw text of rejected records waiting to be written to the quarantine file
+    pub rejected: Vec<String>,
+    // rows read from each input file that contributed to the profile
+    pub input_files: Vec<(String, usize)>,
+    // number of columns declared by the tabular header, anything beyond is a RaggedErr column
+    pub header_len: usize,
+    rng: StdRng,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Profile {
+            column_names: Vec::new(),
+            column_index: HashMap::new(),
+            frequency_maps: Vec::new(),
+            example_maps: Vec::new(),
+            grains: Vec::new(),
+            type_maps: Vec::new(),
+            char_maps: Vec::new(),
+            count_characters: false,
+            semantic_maps: Vec::new(),
+            classifier: None,
+            field_count_map: HashMap::new(),
+            record_count: 0,
+            parse_error_count: 0,
+            parse_errors: Vec::new(),
+            encoding_error_count: 0,
+            encoding_errors: Vec::new(),
+            rejected: Vec::new(),
+            input_files: Vec::new(),
+            header_len: 0,
+            rng: StdRng::from_rng(thread_rng()).expect("failed to seed random number generator"),
+        }
+    }
+
+    // An empty profile with the same column layout, used to seed per-thread profiles so
+    // that positional (tabular) columns line up with the header.
+    pub fn empty_like(&self) -> Self {
+        let mut profile = Profile::new();
+        profile.grains = self.grains.iter().map(|grain| GrainMaps::new(&grain.grain)).collect();
+        for name in &self.column_names {
+            profile.column(name);
+        }
+        profile.header_len = self.header_len;
+        profile.count_characters = self.count_characters;
+        profile.classifier = self.classifier.clone();
+        profile
+    }
+
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.column_index.get(name).copied()
+    }
+
+    // Column names (tabular columns or JSON paths) in header / first-seen order.
+    pub fn columns(&self) -> &[String] {
+        &self.column_names
+    }
+
+    // The masks of a column by descending count, ties broken by the mask itself. Empty for
+    // a column that was never seen.
+    pub fn masks(&self, column: &str) -> Vec<(&str, usize)> {
+        let mut masks = self
+            .column_index(column)
+            .map_or_else(Vec::new, |idx| {
+                self.frequency_maps[idx].iter().map(|(mask, count)| (mask.as_str(), *count)).collect()
+            });
+        masks.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        masks
+    }
+
+    // Number of values of a column that had this mask.
+    pub fn count(&self, column: &str, mask: &str) -> usize {
+        self.column_index(column)
+            .and_then(|idx| self.frequency_maps[idx].get(mask).copied())
+            .unwrap_or(0)
+    }
+
+    // The reservoir sampled example of a mask in a column.
+    pub fn example(&self, column: &str, mask: &str) -> Option<&str> {
+        self.column_index(column)
+            .and_then(|idx| self.example_maps[idx].get(mask))
+            .map(|example| example.as_str())
+    }
+
+    // Returns the index of the named column, registering it if it is new.
+    pub fn column(&mut self, name: &str) -> usize {
+        if let Some(idx) = self.column_index.get(name) {
+            return *idx;
+        }
+        let idx = self.column_names.len();
+        self.column_names.push(name.to_string());
+        self.column_index.insert(name.to_string(), idx);
+        self.frequency_maps.push(HashMap::new());
+        self.example_maps.push(HashMap::new());
+        for grain in &mut self.grains {
+            grain.frequency_maps.push(HashMap::new());
+            grain.example_maps.push(HashMap::new());
+        }
+        self.type_maps.push(HashMap::new());
+        self.char_maps.push(HashMap::new());
+        self.semantic_maps.push(HashMap::new());
+        idx
+    }
+
+    pub fn add_value(&mut self, idx: usize, masked_value: String, value: &str) {
+        if self.count_characters {
+            for c in value.chars() {
+                *self.char_maps[idx].entry(c).or_insert(0) += 1;
+            }
+        }
+        if let Some(classifier) = &self.classifier {
+            let semantic_types = classifier.classify(value).collect::<Vec<&str>>();
+            if !semantic_types.is_empty() {
+                let counts = self.semantic_maps[idx].entry(masked_value.clone()).or_default();
+                for semantic_type in semantic_types {
+                    *counts.entry(semantic_type).or_insert(0) += 1;
+                }
+            }
+        }
+
+        add_sampled(&mut self.rng, &mut self.frequency_maps[idx], &mut self.example_maps[idx], masked_value, value);
+    }
+
+    // Counts a value at one of the further grains, keyed by (first grain mask, grain mask).
+    pub fn add_grain_value(&mut self, grain: usize, idx: usize, masks: (String, String), value: &str) {
+        let grain = &mut self.grains[grain];
+        add_sampled(&mut self.rng, &mut grain.frequency_maps[idx], &mut grain.example_maps[idx], masks, value);
+    }
+
+    pub fn add_parse_error(&mut self, error: ParseError) {
+        self.parse_error_count += 1;
+        if self.parse_errors.len() < PARSE_ERROR_SAMPLE {
+            self.parse_errors.push(error);
+        }
+    }
+
+    // Adds the decoding errors counted while reading an input, and their sample.
+    pub fn add_encoding_errors(&mut self, count: usize, errors: Vec<ParseError>) {
+        self.encoding_error_count += count;
+        let room = PARSE_ERROR_SAMPLE.saturating_sub(self.encoding_errors.len());
+        self.encoding_errors.extend(errors.into_iter().take(room));
+    }
+
+    pub fn add_type(&mut self, idx: usize, json_type: &str) {
+        *self.type_maps[idx].entry(json_type.to_string()).or_insert(0) += 1;
+    }
+
+    // Folds another profile into this one. Columns are matched by name, and further grains
+    // by position, as both profiles are made with the same grains.
+    pub fn merge(&mut self, other: Profile) {
+        let Profile {
+            column_names,
+            frequency_maps,
+            example_maps,
+            grains,
+            type_maps,
+            char_maps,
+            semantic_maps,
+            field_count_map,
+            record_count,
+            parse_error_count,
+            parse_errors,
+            encoding_error_count,
+            encoding_errors,
+            rejected,
+            input_files,
+            header_len,
+            ..
+        } = other;
+
+        let indexes = column_names.iter().map(|name| self.column(name)).collect::<Vec<usize>>();
+        for ((((idx, frequency_map), example_map), type_map), char_map) in
+            indexes.iter().zip(frequency_maps).zip(example_maps).zip(type_maps).zip(char_maps)
+        {
+            for (json_type, count) in type_map {
+                *self.type_maps[*idx].entry(json_type).or_insert(0) += count;
+            }
+            for (c, count) in char_map {
+                *self.char_maps[*idx].entry(c).or_insert(0) += count;
+            }
+            merge_sampled(
+                &mut self.rng,
+                &mut self.frequency_maps[*idx],
+                &mut self.example_maps[*idx],
+                frequency_map,
+                example_map,
+            );
+        }
+        for (idx, semantic_map) in indexes.iter().zip(semantic_maps) {
+            for (mask, counts) in semantic_map {
+                let merged = self.semantic_maps[*idx].entry(mask).or_default();
+                for (semantic_type, count) in counts {
+                    *merged.entry(semantic_type).or_insert(0) += count;
+                }
+            }
+        }
+        for (grain, other_grain) in self.grains.iter_mut().zip(grains) {
+            for ((idx, frequency_map), example_map) in
+                indexes.iter().zip(other_grain.frequency_maps).zip(other_grain.example_maps)
+            {
+                merge_sampled(
+                    &mut self.rng,
+                    &mut grain.frequency_maps[*idx],
+                    &mut grain.example_maps[*idx],
+                    frequency_map,
+                    example_map,
+                );
+            }
+        }
+
+        for (field_count, rows) in field_count_map {
+            *self.field_count_map.entry(field_count).or_insert(0) += rows;
+        }
+        self.record_count += record_count;
+        self.parse_error_count += parse_error_count;
+        let room = PARSE_ERROR_SAMPLE.saturating_sub(self.parse_errors.len());
+        self.parse_errors.extend(parse_errors.into_iter().take(room));
+        self.add_encoding_errors(encoding_error_count, encoding_errors);
+        self.rejected.extend(rejected);
+        self.input_files.extend(input_files);
+        self.header_len = self.header_len.max(header_len);
+    }
+}
@@ -0,0 +1,735 @@
+use std::sync::Arc;
+
+use rayon::prelude::*;
+use serde_json::Value;
+
+use crate::input::InputLine;
+use crate::layout::Layout;
+use crate::mask::{Mask, MaskRegistry};
+use crate::profile::{GrainMaps, ParseError, Profile};
+use crate::semantic::Classifier;
+
+// What happens to JSON objects nested deeper than the path depth limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DepthOverflow {
+    // the whole object is serialized and masked as a single value at the truncated path
+    Collapse,
+    // every leaf below the limit is counted under the truncated path plus a '...' suffix
+    Placeholder,
+}
+
+// How a Profiler reads and masks records. The format is named as on the command line:
+// 'tabular', 'csv', 'json', 'parquet' (rows are fed as JSON values) or 'fixed'.
+pub struct ProfilerOptions {
+    // name of the mask in masks, or a comma separated list of them to profile several
+    // grains in one pass
+    pub grain: String,
+    pub masks: MaskRegistry,
+    pub format: String,
+    // field separator of tabular lines
+    pub delimiter: String,
+    // maximum number of object keys in a JSON path, array indexes do not add depth
+    pub pathdepth: usize,
+    pub remove_array_numbers: bool,
+    pub depth_overflow: DepthOverflow,
+    // the first row of each tabular input is a header rather than data
+    pub has_header: bool,
+    // column names used instead of the header row
+    pub columns: Option<Vec<String>>,
+    // cuts the records of the 'fixed' format into fields
+    pub layout: Option<Layout>,
+    // keep the text of records that fail to parse in Profile::rejected, for a quarantine file
+    pub keep_rejected: bool,
+    // count the characters of every value, for a per-field character profile
+    pub count_characters: bool,
+    // count the values of each mask that conform to each semantic type, for Classifier::infer
+    pub semantic: bool,
+    // number of records a worker thread profiles at a time when a batch is fed
+    pub chunk_size: usize,
+}
+
+impl Default for ProfilerOptions {
+    fn default() -> Self {
+        ProfilerOptions {
+            grain: "LU".to_string(),
+            masks: MaskRegistry::new(),
+            format: "tabular".to_string(),
+            delimiter: "|".to_string(),
+            pathdepth: 2,
+            remove_array_numbers: false,
+            depth_overflow: DepthOverflow::Collapse,
+            has_header: true,
+            columns: None,
+            layout: None,
+            keep_rejected: false,
+            count_characters: false,
+            semantic: false,
+            chunk_size: 10000,
+        }
+    }
+}
+
+// Builds a Profile from records fed one at a time or in batches. Batches are profiled in
+// parallel on the rayon thread pool. Records that fail to parse are counted as parse
+// errors of the profile, positioned by the input name given to start_input.
+pub struct Profiler {
+    options: ProfilerOptions,
+    // the mask of each grain, the first grain's masks are the profile's main masks
+    masks: Vec<Arc<dyn Mask>>,
+    profile: Profile,
+    input_name: String,
+    // set once the header row of the current tabular input has been read
+    header_seen: bool,
+}
+
+impl Profiler {
+    pub fn new(options: ProfilerOptions) -> Result<Profiler, String> {
+        match (options.format.as_str(), &options.layout) {
+            ("tabular", _) | ("csv", _) | ("json", _) | ("parquet", _) | ("fixed", Some(_)) => {}
+            ("fixed", None) => return Err("the fixed format requires a layout".to_string()),
+            (format, _) => return Err(format!("unknown format '{}'", format)),
+        }
+        // a comma separated list of grains is profiled in one pass
+        let grains = options.grain.split(',').map(|grain| grain.trim()).collect::<Vec<&str>>();
+        let mut masks = Vec::new();
+        for (idx, grain) in grains.iter().enumerate() {
+            if grains[..idx].contains(grain) {
+                return Err(format!("grain '{}' is listed twice", grain));
+            }
+            masks.push(options.masks.get(grain)?);
+        }
+        let mut profile = Profile::new();
+        profile.grains = grains[1..].iter().map(|grain| GrainMaps::new(grain)).collect();
+        profile.count_characters = options.count_characters;
+        profile.classifier = options.semantic.then(|| Arc::new(Classifier::new()));
+        if let (Some(layout), "fixed") = (&options.layout, options.format.as_str()) {
+            // columns are reported in layout order, including fields that never occur
+            for name in layout.column_names() {
+                profile.column(name);
+            }
+        }
+        let mut profiler = Profiler { options, masks, profile, input_name: String::new(), header_seen: false };
+        profiler.start_input("");
+        Ok(profiler)
+    }
+
+    pub fn options(&self) -> &ProfilerOptions {
+        &self.options
+    }
+
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    // For bookkeeping the profiler does not do itself, e.g. errors found while reading.
+    pub fn profile_mut(&mut self) -> &mut Profile {
+        &mut self.profile
+    }
+
+    pub fn finish(self) -> Profile {
+        self.profile
+    }
+
+    // Starts the next input (a file, say). Each tabular input carries its own header row.
+    pub fn start_input(&mut self, name: &str) {
+        self.input_name = name.to_string();
+        self.header_seen = !matches!(self.options.format.as_str(), "tabular" | "csv");
+    }
+
+    // Profiles a raw line of the input, without its terminator: a tabular row (the first
+    // row of an input is its header), a JSON document or a fixed-width record. The error
+    // of a record that does not parse is counted in the profile and returned.
+    pub fn feed_line(&mut self, text: &str) -> Result<(), String> {
+        if !self.header_seen {
+            let fields = text.split(self.options.delimiter.as_str()).collect::<Vec<&str>>();
+            if self.take_header(&fields) {
+                return Ok(());
+            }
+        }
+        self.profile.record_count += 1;
+        let result = profile_line(&self.options, &self.masks, text, &mut self.profile);
+        result.map_err(|(column, message)| {
+            let line = InputLine { number: self.profile.record_count, offset: 0, text: text.to_string() };
+            reject(&self.options, &self.input_name, &line, column, message.clone(), &mut self.profile);
+            message
+        })
+    }
+
+    // Profiles a batch of lines in parallel, as feed_line does.
+    pub fn feed_lines(&mut self, lines: &[InputLine]) {
+        let mut lines = lines;
+        if !self.header_seen {
+            if let Some((first, rest)) = lines.split_first() {
+                let fields = first.text.split(self.options.delimiter.as_str()).collect::<Vec<&str>>();
+                if self.take_header(&fields) {
+                    lines = rest;
+                }
+            }
+        }
+        let options = &self.options;
+        let masks = &self.masks;
+        let input_name = &self.input_name;
+        let batch_profile = profile_batch(lines, &self.profile, options.chunk_size, |line, profile| {
+            if let Err((column, message)) = profile_line(options, masks, &line.text, profile) {
+                reject(options, input_name, line, column, message, profile);
+            }
+        });
+        self.profile.merge(batch_profile);
+    }
+
+    // Profiles a row of tabular fields; the first row of an input is its header.
+    pub fn feed_fields(&mut self, fields: &[&str]) {
+        if !self.header_seen && self.take_header(fields) {
+            return;
+        }
+        self.profile.record_count += 1;
+        process_tabular_fields(fields, &mut self.profile, &self.masks);
+    }
+
+    // Profiles a batch of csv records in parallel, as feed_fields does.
+    pub fn feed_csv_records(&mut self, records: &[csv::StringRecord]) {
+        let mut records = records;
+        if !self.header_seen {
+            if let Some((first, rest)) = records.split_first() {
+                if self.take_header(&first.iter().collect::<Vec<&str>>()) {
+                    records = rest;
+                }
+            }
+        }
+        let masks = &self.masks;
+        let batch_profile = profile_batch(records, &self.profile, self.options.chunk_size, |record, profile| {
+            let fields = record.iter().collect::<Vec<&str>>();
+            process_tabular_fields(&fields, profile, masks);
+        });
+        self.profile.merge(batch_profile);
+    }
+
+    // Profiles a JSON record (or a Parquet row rendered as JSON) by the paths of its leaves.
+    pub fn feed_record(&mut self, record: &Value) {
+        self.profile.record_count += 1;
+        process_json_value(record, &mut self.profile, &self.options, &self.masks, String::new(), 0);
+    }
+
+    // Profiles a batch of JSON records in parallel.
+    pub fn feed_records(&mut self, records: &[Value]) {
+        let options = &self.options;
+        let masks = &self.masks;
+        let batch_profile = profile_batch(records, &self.profile, options.chunk_size, |record, profile| {
+            process_json_value(record, profile, options, masks, String::new(), 0);
+        });
+        self.profile.merge(batch_profile);
+    }
+
+    // Takes the first row of a tabular input as its header, or only names the columns when
+    // the input has none. Returns whether the row was a header rather than data.
+    fn take_header(&mut self, first_row: &[&str]) -> bool {
+        self.header_seen = true;
+        // headerless inputs after the first only have to have the same number of fields
+        if !self.options.has_header && !self.profile.column_names.is_empty() {
+            if first_row.len() != self.profile.header_len {
+                eprintln!(
+                    "Warning: first row of {} has {} fields instead of the {} columns of the first input",
+                    self.input_name,
+                    first_row.len(),
+                    self.profile.header_len
+                );
+            }
+            return false;
+        }
+        let names = header_names(first_row, self.options.columns.as_deref(), self.options.has_header);
+        if !process_tabular_header(&names, &mut self.profile) {
+            eprintln!("Warning: header of {} differs from the first input's header", self.input_name);
+        }
+        if self.options.has_header {
+            self.profile.record_count += 1;
+        }
+        self.options.has_header
+    }
+}
+
+// Profiles a line in the profiler's format. A record that does not parse is returned with
+// the character position of the error within the line and its message.
+fn profile_line(options: &ProfilerOptions, masks: &[Arc<dyn Mask>], text: &str, profile: &mut Profile) -> Result<(), (u64, String)> {
+    match (options.format.as_str(), &options.layout) {
+        ("json", _) => process_json_line(text, profile, options, masks)
+            .map_err(|e| (e.column().saturating_sub(1) as u64, json_error_message(&e))),
+        ("fixed", Some(layout)) => match process_fixed_width_record(text, profile, layout, masks) {
+            Some(message) => Err((0, message)),
+            None => Ok(()),
+        },
+        _ => {
+            let fields = text.split(options.delimiter.as_str()).collect::<Vec<&str>>();
+            process_tabular_fields(&fields, profile, masks);
+            Ok(())
+        }
+    }
+}
+
+fn reject(options: &ProfilerOptions, input_name: &str, line: &InputLine, column: u64, message: String, profile: &mut Profile) {
+    profile.add_parse_error(ParseError {
+        file: input_name.to_string(),
+        line: line.number,
+        offset: line.offset + column,
+        message,
+    });
+    if options.keep_rejected {
+        profile.rejected.push(line.text.clone());
+    }
+}
+
+// The JSON type of a leaf, and its text as it is masked: strings without their quotes and
+// escapes, everything else in its JSON serialization.
+fn json_leaf(value: &Value) -> (&'static str, String) {
+    match value {
+        Value::String(text) => ("string", text.clone()),
+        Value::Number(number) if number.is_i64() || number.is_u64() => ("integer", number.to_string()),
+        Value::Number(number) => ("number", number.to_string()),
+        Value::Bool(flag) => ("bool", flag.to_string()),
+        Value::Null => ("null", "null".to_string()),
+        Value::Array(_) => ("array", value.to_string()),
+        Value::Object(_) => ("object", value.to_string()),
+    }
+}
+
+// Strings carry the plain mask so they line up with tabular masks; other types are tagged
+// with their type so that e.g. the number 123 and the string "123" are not conflated.
+fn typed_mask(json_type: &str, text: &str, mask: &dyn Mask) -> String {
+    match json_type {
+        "string" => mask.mask(text),
+        "null" | "bool" => format!("<{}>", json_type),
+        _ => format!("<{}>{}", json_type, mask.mask(text)),
+    }
+}
+
+// Counts a value of a column under its mask at every grain. The masks of JSON leaves are
+// tagged with their type.
+fn add_masked_value(profile: &mut Profile, masks: &[Arc<dyn Mask>], idx: usize, value: &str, json_type: Option<&str>) {
+    let mask_at = |mask: &Arc<dyn Mask>| match json_type {
+        Some(json_type) => typed_mask(json_type, value, mask.as_ref()),
+        None => mask.mask(value),
+    };
+    let first_mask = mask_at(&masks[0]);
+    for (grain, mask) in masks[1..].iter().enumerate() {
+        profile.add_grain_value(grain, idx, (first_mask.clone(), mask_at(mask)), value);
+    }
+    profile.add_value(idx, first_mask, value);
+}
+
+fn add_json_leaf(profile: &mut Profile, masks: &[Arc<dyn Mask>], path: &str, value: &Value) {
+    let (json_type, text) = json_leaf(value);
+    let idx = profile.column(path);
+    add_masked_value(profile, masks, idx, &text, Some(json_type));
+    profile.add_type(idx, json_type);
+}
+
+fn process_json_value(
+    value: &Value,
+    profile: &mut Profile,
+    options: &ProfilerOptions,
+    masks: &[Arc<dyn Mask>],
+    prefix: String,
+    current_depth: usize,
+) {
+    match value {
+        Value::Object(_) if current_depth >= options.pathdepth => match options.depth_overflow {
+            DepthOverflow::Collapse => add_json_leaf(profile, masks, &prefix, value),
+            DepthOverflow::Placeholder => process_json_overflow(value, profile, masks, &format!("{}...", prefix)),
+        },
+        Value::Object(map) => {
+            for (key, value) in map.iter() {
+                let full_key = if prefix.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                process_json_value(value, profile, options, masks, full_key, current_depth + 1);
+            }
+        }
+        Value::Array(values) => {
+            for (idx, value) in values.iter().enumerate() {
+                let full_key = if options.remove_array_numbers {
+                    format!("{}[]", prefix)
+                } else {
+                    format!("{}[{}]", prefix, idx)
+                };
+                process_json_value(value, profile, options, masks, full_key, current_depth);
+            }
+        }
+        _ => add_json_leaf(profile, masks, &prefix, value),
+    }
+}
+
+// Counts every leaf below the depth limit under the single placeholder path.
+fn process_json_overflow(value: &Value, profile: &mut Profile, masks: &[Arc<dyn Mask>], path: &str) {
+    match value {
+        Value::Object(map) => {
+            for value in map.values() {
+                process_json_overflow(value, profile, masks, path);
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                process_json_overflow(value, profile, masks, path);
+            }
+        }
+        _ => add_json_leaf(profile, masks, path, value),
+    }
+}
+
+// serde_json appends the position within the record, which is reported separately.
+fn json_error_message(error: &serde_json::Error) -> String {
+    let message = error.to_string();
+    match message.rsplit_once(" at line ") {
+        Some((message, _)) => message.to_string(),
+        None => message,
+    }
+}
+
+fn process_json_line(line: &str, profile: &mut Profile, options: &ProfilerOptions, masks: &[Arc<dyn Mask>]) -> Result<(), serde_json::Error> {
+    let json_value = serde_json::from_str::<Value>(line)?;
+    process_json_value(&json_value, profile, options, masks, String::new(), 0);
+    Ok(())
+}
+
+// The column names for the first row of a tabular input: the columns override, the row
+// itself when it is a header, or col_1, col_2, ... for headerless data.
+fn header_names(first_row: &[&str], columns: Option<&[String]>, has_header: bool) -> Vec<String> {
+    match columns {
+        Some(columns) => columns.to_vec(),
+        None if has_header => first_row.iter().map(|name| name.to_string()).collect(),
+        None => (1..=first_row.len()).map(|n| format!("col_{}", n)).collect(),
+    }
+}
+
+// Registers the header columns. When the columns are already known (e.g. the header of a
+// second input file) nothing is registered, and false is returned if the header differs.
+fn process_tabular_header(header: &[String], profile: &mut Profile) -> bool {
+    let names = header
+        .iter()
+        .map(|s| s.trim().replace(' ', "_"))
+        .collect::<Vec<String>>();
+
+    if !profile.column_names.is_empty() {
+        return names
+            .iter()
+            .enumerate()
+            .all(|(idx, name)| profile.column_index(name) == Some(idx));
+    }
+
+    for name in &names {
+        profile.column(name);
+    }
+    profile.header_len = names.len();
+    true
+}
+
+fn process_tabular_fields(fields: &[&str], profile: &mut Profile, masks: &[Arc<dyn Mask>]) {
+    if profile.column_names.is_empty() {
+        return;
+    }
+
+    *profile.field_count_map.entry(fields.len()).or_insert(0) += 1;
+
+    for (i, value) in fields.iter().enumerate() {
+        let idx = if i < profile.column_names.len() {
+            i
+        } else {
+            // Register the extra column for ragged rows
+            let extra_column_index = i + 1 - profile.header_len;
+            profile.column(&format!("RaggedErr{}", extra_column_index))
+        };
+
+        add_masked_value(profile, masks, idx, value, None);
+    }
+}
+
+// Profiles the fields of a fixed-width record. Returns why the record does not fit the
+// layout: unknown record types are skipped, records of the wrong length are still profiled.
+fn process_fixed_width_record(text: &str, profile: &mut Profile, layout: &Layout, masks: &[Arc<dyn Mask>]) -> Option<String> {
+    let record = match layout.split(text) {
+        Ok(record) => record,
+        Err(message) => return Some(message),
+    };
+    *profile.field_count_map.entry(record.fields.len()).or_insert(0) += 1;
+    for (column, value) in record.fields {
+        let idx = profile.column(column);
+        add_masked_value(profile, masks, idx, value, None);
+    }
+    record.length_error
+}
+
+// Profiles a batch of records in parallel: the batch is split into chunks, each chunk is
+// profiled into its own Profile (seeded with the template's column layout) by a rayon
+// worker, and the per-chunk profiles are merged.
+fn profile_batch<T, F>(batch: &[T], template: &Profile, chunk_size: usize, process: F) -> Profile
+where
+    T: Sync,
+    F: Fn(&T, &mut Profile) + Sync,
+{
+    batch
+        .par_chunks(chunk_size.max(1))
+        .map(|chunk| {
+            let mut profile = template.empty_like();
+            for record in chunk {
+                process(record, &mut profile);
+                profile.record_count += 1;
+            }
+            profile
+        })
+        .reduce(
+            || template.empty_like(),
+            |mut left, right| {
+                left.merge(right);
+                left
+            },
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mask::LowGrainUnicode;
+    use std::collections::HashMap;
+
+    fn json_profiler(pathdepth: usize, depth_overflow: DepthOverflow) -> Profiler {
+        Profiler::new(ProfilerOptions {
+            format: "json".to_string(),
+            pathdepth,
+            depth_overflow,
+            ..ProfilerOptions::default()
+        })
+        .unwrap()
+    }
+
+    fn profile_example(pathdepth: usize, depth_overflow: DepthOverflow) -> Profile {
+        let mut profiler = json_profiler(pathdepth, depth_overflow);
+        for line in include_str!("../testdata/example.json").lines() {
+            profiler.feed_line(line).unwrap();
+        }
+        profiler.finish()
+    }
+
+    fn mask_counts(profile: &Profile, column: &str) -> HashMap<String, usize> {
+        let idx = profile.column_index(column).unwrap_or_else(|| panic!("missing column {}", column));
+        profile.frequency_maps[idx].clone()
+    }
+
+    #[test]
+    fn full_depth_profiles_every_leaf() {
+        let profile = profile_example(3, DepthOverflow::Collapse);
+        assert_eq!(profile.columns(), ["customer.address.postcode", "organisation.address.postcode"]);
+        assert_eq!(mask_counts(&profile, "customer.address.postcode").values().sum::<usize>(), 5);
+        assert_eq!(mask_counts(&profile, "organisation.address.postcode").values().sum::<usize>(), 5);
+        assert_eq!(profile.record_count, 5);
+    }
+
+    #[test]
+    fn collapse_masks_deeper_objects_as_a_whole() {
+        let profile = profile_example(2, DepthOverflow::Collapse);
+        assert_eq!(profile.columns(), ["customer.address", "organisation.address"]);
+
+        let expected_mask = format!("<object>{}", LowGrainUnicode.mask(r#"{"postcode":"12345"}"#));
+        assert_eq!(profile.count("customer.address", &expected_mask), 5);
+        assert_eq!(profile.type_maps[profile.column_index("customer.address").unwrap()]["object"], 5);
+
+        let example = profile.example("customer.address", &expected_mask).unwrap();
+        assert!(serde_json::from_str::<Value>(example).unwrap()["postcode"].is_string());
+    }
+
+    #[test]
+    fn strings_are_masked_without_json_quotes() {
+        let profile = profile_example(3, DepthOverflow::Collapse);
+        assert_eq!(profile.masks("customer.address.postcode"), [("9", 5)]);
+        let idx = profile.column_index("customer.address.postcode").unwrap();
+        assert_eq!(profile.type_maps[idx].get("string"), Some(&5));
+        assert!(!profile.example("customer.address.postcode", "9").unwrap().contains('"'));
+    }
+
+    #[test]
+    fn leaf_types_are_kept_apart() {
+        let mut profiler = json_profiler(2, DepthOverflow::Collapse);
+        for line in [r#"{"v": "123"}"#, r#"{"v": 123}"#, r#"{"v": 1.5}"#, r#"{"v": null}"#, r#"{"v": true}"#] {
+            profiler.feed_line(line).unwrap();
+        }
+        let profile = profiler.finish();
+        for mask in ["9", "<integer>9", "<number>9.9", "<null>", "<bool>"] {
+            assert_eq!(profile.count("v", mask), 1, "mask {}", mask);
+        }
+        assert_eq!(profile.type_maps[0].len(), 5);
+    }
+
+    #[test]
+    fn placeholder_counts_deeper_leaves_under_truncated_path() {
+        let profile = profile_example(1, DepthOverflow::Placeholder);
+        assert_eq!(profile.columns(), ["customer...", "organisation..."]);
+        assert_eq!(mask_counts(&profile, "customer...").values().sum::<usize>(), 5);
+        assert_eq!(mask_counts(&profile, "organisation...").values().sum::<usize>(), 5);
+    }
+
+    #[test]
+    fn zero_depth_collapses_the_whole_record() {
+        let profile = profile_example(0, DepthOverflow::Collapse);
+        assert_eq!(profile.columns(), [""]);
+        assert_eq!(mask_counts(&profile, "").values().sum::<usize>(), 5);
+    }
+
+    #[test]
+    fn array_indexes_do_not_add_depth() {
+        let mut profiler = Profiler::new(ProfilerOptions {
+            format: "json".to_string(),
+            remove_array_numbers: true,
+            ..ProfilerOptions::default()
+        })
+        .unwrap();
+        profiler.feed_record(&serde_json::json!({"devices": [{"location": {"county": "Kent"}}]}));
+        assert_eq!(profiler.profile().columns(), ["devices[].location"]);
+    }
+
+    #[test]
+    fn invalid_json_lines_are_counted_and_kept() {
+        let mut profiler = Profiler::new(ProfilerOptions {
+            format: "json".to_string(),
+            keep_rejected: true,
+            ..ProfilerOptions::default()
+        })
+        .unwrap();
+        profiler.start_input("feed.json");
+        profiler.feed_line(r#"{"a": 1}"#).unwrap();
+        assert!(profiler.feed_line(r#"{"a": "#).is_err());
+        let profile = profiler.finish();
+        assert_eq!(profile.record_count, 2);
+        assert_eq!(profile.parse_error_count, 1);
+        assert_eq!(profile.parse_errors[0].file, "feed.json");
+        assert_eq!(profile.parse_errors[0].line, 2);
+        assert_eq!(profile.rejected, [r#"{"a": "#]);
+    }
+
+    #[test]
+    fn json_errors_are_positioned_at_the_offending_byte() {
+        let mut profiler = Profiler::new(ProfilerOptions { format: "json".to_string(), ..ProfilerOptions::default() }).unwrap();
+        profiler.feed_lines(&[InputLine { number: 3, offset: 100, text: r#"{"a": oops}"#.to_string() }]);
+        let profile = profiler.finish();
+        assert_eq!((profile.parse_errors[0].line, profile.parse_errors[0].offset), (3, 106));
+    }
+
+    #[test]
+    fn first_tabular_line_is_the_header() {
+        let mut profiler = Profiler::new(ProfilerOptions { grain: "H".to_string(), ..ProfilerOptions::default() }).unwrap();
+        for line in ["id|post code", "1|SW1 1AA", "22|EC1A 1BB", "3|N1 9GU|extra"] {
+            profiler.feed_line(line).unwrap();
+        }
+        let profile = profiler.finish();
+        assert_eq!(profile.columns(), ["id", "post_code", "RaggedErr1"]);
+        assert_eq!(profile.masks("id"), [("9", 2), ("99", 1)]);
+        assert_eq!(profile.example("post_code", "AA9A 9AA"), Some("EC1A 1BB"));
+        assert_eq!(profile.count("RaggedErr1", "aaaaa"), 1);
+        assert_eq!(profile.record_count, 4);
+    }
+
+    #[test]
+    fn headerless_rows_are_profiled_as_data() {
+        let mut profiler = Profiler::new(ProfilerOptions { has_header: false, ..ProfilerOptions::default() }).unwrap();
+        profiler.feed_fields(&["1", "abc"]);
+        profiler.feed_fields(&["2", "de"]);
+        let profile = profiler.finish();
+        assert_eq!(profile.columns(), ["col_1", "col_2"]);
+        assert_eq!(profile.count("col_1", "9"), 2);
+        assert_eq!(profile.count("col_2", "a"), 2);
+    }
+
+    #[test]
+    fn wider_headerless_inputs_keep_the_column_names() {
+        let mut profiler = Profiler::new(ProfilerOptions { has_header: false, ..ProfilerOptions::default() }).unwrap();
+        profiler.start_input("first");
+        profiler.feed_fields(&["1", "abc"]);
+        profiler.start_input("second");
+        profiler.feed_fields(&["2", "de", "x"]);
+        let profile = profiler.finish();
+        assert_eq!(profile.columns(), ["col_1", "col_2", "RaggedErr1"]);
+        assert_eq!(profile.count("col_1", "9"), 2);
+        assert_eq!(profile.record_count, 2);
+    }
+
+    #[test]
+    fn batches_match_records_fed_one_at_a_time() {
+        let options = || ProfilerOptions { chunk_size: 3, ..ProfilerOptions::default() };
+        let lines = ["name|n"]
+            .iter()
+            .map(|line| line.to_string())
+            .chain((0..20).map(|n| format!("row{}|{}", n, n * 7)))
+            .collect::<Vec<String>>();
+
+        let mut single = Profiler::new(options()).unwrap();
+        for line in &lines {
+            single.feed_line(line).unwrap();
+        }
+        let mut batched = Profiler::new(options()).unwrap();
+        let batch = lines
+            .iter()
+            .enumerate()
+            .map(|(idx, text)| InputLine { number: idx + 1, offset: 0, text: text.clone() })
+            .collect::<Vec<InputLine>>();
+        batched.feed_lines(&batch);
+
+        let (single, batched) = (single.finish(), batched.finish());
+        assert_eq!(single.record_count, batched.record_count);
+        for column in ["name", "n"] {
+            assert_eq!(single.masks(column), batched.masks(column));
+        }
+    }
+
+    #[test]
+    fn grains_are_looked_up_in_the_registry() {
+        let mut masks = MaskRegistry::new();
+        masks.register("LEN", |value: &str| value.len().to_string());
+        let mut profiler = Profiler::new(ProfilerOptions {
+            grain: "LEN".to_string(),
+            masks,
+            ..ProfilerOptions::default()
+        })
+        .unwrap();
+        for line in ["code", "AB1", "XY", "CD2"] {
+            profiler.feed_line(line).unwrap();
+        }
+        assert_eq!(profiler.finish().masks("code"), [("3", 2), ("2", 1)]);
+
+        let options = ProfilerOptions { grain: "X".to_string(), ..ProfilerOptions::default() };
+        assert!(Profiler::new(options).is_err());
+    }
+
+    #[test]
+    fn several_grains_in_one_pass() {
+        let mut profiler = Profiler::new(ProfilerOptions { grain: "LU,H".to_string(), ..ProfilerOptions::default() }).unwrap();
+        for line in ["postcode", "SW1 1AA", "EC1A 1BB", "N1 9GU", "W1A 1AA"] {
+            profiler.feed_line(line).unwrap();
+        }
+        let profile = profiler.finish();
+        assert_eq!(profile.masks("postcode"), [("A9 9A", 2), ("A9A 9A", 2)]);
+
+        let high = crate::report::grain_masks(&profile, 0, 0);
+        let high = high.iter().map(|mask| (mask.mask, mask.count)).collect::<Vec<(&str, usize)>>();
+        assert_eq!(high, [("A9 9AA", 1), ("A9A 9AA", 1), ("AA9 9AA", 1), ("AA9A 9AA", 1)]);
+
+        // the high grain masks are nested under the low grain mask they belong to
+        let (first_is_coarser, nested) = crate::report::drill_down(&profile, 0, 0);
+        assert!(first_is_coarser);
+        assert_eq!((nested[0].mask, nested[0].count), ("A9 9A", 2));
+        let children = nested[0].children.iter().map(|mask| mask.mask).collect::<Vec<&str>>();
+        assert_eq!(children, ["A9 9AA", "AA9 9AA"]);
+        assert_eq!(nested[1].children[1].example, Some("EC1A 1BB"));
+    }
+
+    #[test]
+    fn repeated_grains_are_rejected() {
+        let options = ProfilerOptions { grain: "H,L,H".to_string(), ..ProfilerOptions::default() };
+        assert!(Profiler::new(options).is_err());
+    }
+
+    #[test]
+    fn unknown_formats_are_rejected() {
+        let options = ProfilerOptions { format: "xml".to_string(), ..ProfilerOptions::default() };
+        assert!(Profiler::new(options).is_err());
+        let options = ProfilerOptions { format: "fixed".to_string(), ..ProfilerOptions::default() };
+        assert!(Profiler::new(options).is_err());
+    }
+}
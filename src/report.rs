@@ -0,0 +1,435 @@
+use std::collections::HashMap;
+
+use chrono::Local;
+use serde_json::{json, Value};
+
+use crate::profile::Profile;
+use crate::semantic::{Classifier, Inference};
+
+// Number of nonconforming masks listed per column in the text report's semantic types.
+const SEMANTIC_TEXT_MASKS: usize = 10;
+
+// The settings a profile was produced with, echoed in the report header.
+pub struct RunInfo {
+    pub grain: String,
+    pub delimiter: String,
+    pub format: String,
+    // what was guessed about the input when the format or delimiter was left to 'auto'
+    pub detected: Option<String>,
+}
+
+impl RunInfo {
+    // The grain of the profile's main masks, the first of a multi-grain run.
+    pub fn first_grain(&self) -> &str {
+        self.grain.split(',').next().unwrap_or("").trim()
+    }
+}
+
+// A profile made with a classifier also gets the semantic type inferred for each column.
+pub fn print_report(profile: &Profile, info: &RunInfo, output_format: &str, column_order: &str) {
+    let columns = ordered_columns(profile, column_order);
+    let classifier = profile.classifier.as_deref();
+    if output_format == "json" {
+        print_json_report(profile, info, &columns, classifier);
+    } else {
+        print_text_report(profile, info, &columns, classifier);
+    }
+}
+
+// Column indexes in report order:
+//   'header' - header / first-seen order (the column index order)
+//   'name'   - alphabetically by column name
+//   'masks'  - most distinct masks first, ties in header order
+pub fn ordered_columns(profile: &Profile, column_order: &str) -> Vec<usize> {
+    let mut columns = (0..profile.column_names.len()).collect::<Vec<usize>>();
+    match column_order {
+        "name" => columns.sort_by(|a, b| profile.column_names[*a].cmp(&profile.column_names[*b])),
+        "masks" => columns.sort_by(|a, b| profile.frequency_maps[*b].len().cmp(&profile.frequency_maps[*a].len())),
+        _ => {}
+    }
+    columns
+}
+
+// Masks by descending count, ties broken by the mask itself so reports diff cleanly.
+pub fn sorted_masks(profile: &Profile, idx: usize) -> Vec<(&String, &usize)> {
+    let mut column_counts = profile.frequency_maps[idx].iter().collect::<Vec<(&String, &usize)>>();
+    column_counts.sort_unstable_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    column_counts
+}
+
+// JSON types by descending count, ties broken by name.
+pub fn sorted_types(profile: &Profile, idx: usize) -> Vec<(&String, &usize)> {
+    let mut types = profile.type_maps[idx].iter().collect::<Vec<(&String, &usize)>>();
+    types.sort_unstable_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    types
+}
+
+// A mask with its count and an example; in a drill-down, with the masks of the finer grain
+// nested under it.
+pub struct NestedMask<'a> {
+    pub mask: &'a str,
+    pub count: usize,
+    pub example: Option<&'a str>,
+    pub children: Vec<NestedMask<'a>>,
+}
+
+// Groups the (first grain mask, grain mask) pairs of a further grain under the first or
+// the second mask of the pair. A group's example is that of its most frequent member.
+fn group_pairs(profile: &Profile, grain: usize, idx: usize, by_first: bool) -> Vec<NestedMask<'_>> {
+    let maps = &profile.grains[grain];
+    let mut groups: HashMap<&str, Vec<NestedMask>> = HashMap::new();
+    for (pair, count) in &maps.frequency_maps[idx] {
+        let (parent, child) = if by_first { (&pair.0, &pair.1) } else { (&pair.1, &pair.0) };
+        groups.entry(parent).or_default().push(NestedMask {
+            mask: child,
+            count: *count,
+            example: maps.example_maps[idx].get(pair).map(|example| example.as_str()),
+            children: Vec::new(),
+        });
+    }
+    let by_count = |a: &NestedMask, b: &NestedMask| b.count.cmp(&a.count).then_with(|| a.mask.cmp(b.mask));
+    let mut masks = groups
+        .into_iter()
+        .map(|(mask, mut children)| {
+            children.sort_unstable_by(by_count);
+            NestedMask {
+                mask,
+                count: children.iter().map(|child| child.count).sum(),
+                example: children[0].example,
+                children,
+            }
+        })
+        .collect::<Vec<NestedMask>>();
+    masks.sort_unstable_by(by_count);
+    masks
+}
+
+// The masks of a column at a further grain, by descending count.
+pub fn grain_masks(profile: &Profile, grain: usize, idx: usize) -> Vec<NestedMask<'_>> {
+    let mut masks = group_pairs(profile, grain, idx, false);
+    for mask in &mut masks {
+        mask.children.clear();
+    }
+    masks
+}
+
+// The masks of the finer of the first grain and a further grain, nested under the masks of
+// the coarser one (the grain with fewer distinct masks in the column). Returns whether the
+// first grain is the coarser one.
+pub fn drill_down(profile: &Profile, grain: usize, idx: usize) -> (bool, Vec<NestedMask<'_>>) {
+    let by_grain = group_pairs(profile, grain, idx, false);
+    if by_grain.len() < profile.frequency_maps[idx].len() {
+        return (false, by_grain);
+    }
+    let mut by_first = group_pairs(profile, grain, idx, true);
+    // the first grain's own reservoir gives an example sampled over all of its values
+    for mask in &mut by_first {
+        mask.example = profile.example_maps[idx].get(mask.mask).map(|example| example.as_str()).or(mask.example);
+    }
+    (true, by_first)
+}
+
+// Names of the coarser and finer grain of a drill-down.
+fn drill_down_grains<'a>(profile: &'a Profile, info: &'a RunInfo, grain: usize, first_is_coarser: bool) -> (&'a str, &'a str) {
+    let first = info.first_grain();
+    let other = profile.grains[grain].grain.as_str();
+    if first_is_coarser {
+        (first, other)
+    } else {
+        (other, first)
+    }
+}
+
+fn sorted_field_counts(profile: &Profile) -> Vec<(&usize, &usize)> {
+    let mut fields_per_line = profile.field_count_map.iter().collect::<Vec<(&usize, &usize)>>();
+    fields_per_line.sort_unstable();
+    fields_per_line
+}
+
+fn print_json_report(profile: &Profile, info: &RunInfo, columns: &[usize], classifier: Option<&Classifier>) {
+    let fields_per_line = sorted_field_counts(profile);
+
+    let report = json!({
+        "metadata": {
+            "timestamp": Local::now().to_rfc3339(),
+            "grain": info.grain,
+            "delimiter": info.delimiter,
+            "format": info.format,
+            "detected": info.detected,
+            "record_count": profile.record_count,
+            "parse_error_count": profile.parse_error_count,
+            "encoding_error_count": profile.encoding_error_count,
+            "input_files": profile.input_files
+                .iter()
+                .map(|(file_name, rows)| json!({ "file": file_name, "rows": rows }))
+                .collect::<Vec<Value>>(),
+        },
+        "parse_errors": profile.parse_errors
+            .iter()
+            .map(|error| json!({
+                "file": error.file,
+                "line": error.line,
+                "offset": error.offset,
+                "message": error.message,
+            }))
+            .collect::<Vec<Value>>(),
+        "encoding_errors": profile.encoding_errors
+            .iter()
+            .map(|error| json!({
+                "file": error.file,
+                "line": error.line,
+                "offset": error.offset,
+                "message": error.message,
+            }))
+            .collect::<Vec<Value>>(),
+        "fields_per_line": fields_per_line
+            .iter()
+            .map(|(field_count, rows)| json!({ "fields": field_count, "rows": rows }))
+            .collect::<Vec<Value>>(),
+        "columns": columns
+            .iter()
+            .map(|&idx| {
+                let mut column = json!({
+                    "index": idx,
+                    "name": profile.column_names[idx],
+                    "types": sorted_types(profile, idx)
+                        .iter()
+                        .map(|(json_type, count)| json!({ "type": json_type, "count": count }))
+                        .collect::<Vec<Value>>(),
+                    "masks": sorted_masks(profile, idx)
+                        .iter()
+                        .map(|(mask, count)| json!({
+                            "mask": mask,
+                            "count": count,
+                            "example": profile.example_maps[idx].get(*mask),
+                        }))
+                        .collect::<Vec<Value>>(),
+                });
+                if !profile.grains.is_empty() {
+                    column["grains"] = json_grains(profile, info, idx);
+                }
+                if let Some(classifier) = classifier {
+                    column["semantic"] = json_semantic(classifier.infer(profile, idx));
+                }
+                column
+            })
+            .collect::<Vec<Value>>(),
+    });
+    println!("{}", serde_json::to_string_pretty(&report).unwrap());
+}
+
+fn json_masks(masks: &[NestedMask]) -> Value {
+    masks
+        .iter()
+        .map(|mask| {
+            let mut entry = json!({ "mask": mask.mask, "count": mask.count, "example": mask.example });
+            if !mask.children.is_empty() {
+                entry["children"] = json_masks(&mask.children);
+            }
+            entry
+        })
+        .collect::<Vec<Value>>()
+        .into()
+}
+
+// The inferred semantic type of a column, or null when no type fits enough of its values.
+fn json_semantic(inference: Option<Inference>) -> Value {
+    match inference {
+        None => Value::Null,
+        Some(inference) => json!({
+            "type": inference.semantic_type,
+            "conforming": inference.conforming,
+            "share": inference.share(),
+            "nonconforming_masks": inference.nonconforming
+                .iter()
+                .map(|(mask, count, example)| json!({ "mask": mask, "count": count, "example": example }))
+                .collect::<Vec<Value>>(),
+        }),
+    }
+}
+
+// The masks of a column at each further grain, with the drill-down between it and the first grain.
+fn json_grains(profile: &Profile, info: &RunInfo, idx: usize) -> Value {
+    (0..profile.grains.len())
+        .map(|grain| {
+            let (first_is_coarser, nested) = drill_down(profile, grain, idx);
+            let (parent, child) = drill_down_grains(profile, info, grain, first_is_coarser);
+            json!({
+                "grain": profile.grains[grain].grain,
+                "masks": json_masks(&grain_masks(profile, grain, idx)),
+                "drill_down": {
+                    "parent_grain": parent,
+                    "child_grain": child,
+                    "masks": json_masks(&nested),
+                },
+            })
+        })
+        .collect::<Vec<Value>>()
+        .into()
+}
+
+fn print_text_report(profile: &Profile, info: &RunInfo, columns: &[usize], classifier: Option<&Classifier>) {
+    let now_string = Local::now().format("%Y%m%d %H:%M:%S").to_string();
+    println!();
+    println!("Data Profiling Report: {}", now_string);
+    println!("Examined rows: {}", profile.record_count);
+    println!("Parse errors: {}", profile.parse_error_count);
+    if profile.encoding_error_count > 0 {
+        println!("Encoding errors: {}", profile.encoding_error_count);
+    }
+    if let Some(detected) = &info.detected {
+        println!("Detected: {}", detected);
+    }
+    println!();
+    if !profile.parse_errors.is_empty() {
+        println!("ParseErrors:");
+        for error in &profile.parse_errors {
+            println!("{}: line {} (byte {}): {}", error.file, error.line, error.offset, error.message);
+        }
+        if profile.parse_error_count > profile.parse_errors.len() {
+            println!("... {} more", profile.parse_error_count - profile.parse_errors.len());
+        }
+        println!();
+    }
+    if !profile.encoding_errors.is_empty() {
+        println!("EncodingErrors:");
+        for error in &profile.encoding_errors {
+            println!("{}: line {} (byte {}): {}", error.file, error.line, error.offset, error.message);
+        }
+        if profile.encoding_error_count > profile.encoding_errors.len() {
+            println!("... {} more", profile.encoding_error_count - profile.encoding_errors.len());
+        }
+        println!();
+    }
+    println!("InputFiles:");
+    for (file_name, rows) in &profile.input_files {
+        println!("{}: {} rows", file_name, rows);
+    }
+    println!();
+    println!("FieldsPerLine:");
+    // Print the field count map
+    for (field_count, frequency) in sorted_field_counts(profile) {
+        println!("{} fields: {} rows", field_count, frequency);
+    }
+
+    // Print the JSON type histogram of each path, flagging paths with mixed types
+    if profile.type_maps.iter().any(|type_map| !type_map.is_empty()) {
+        println!();
+        println!("JsonTypes:");
+        for &idx in columns {
+            let types = sorted_types(profile, idx);
+            if types.is_empty() {
+                continue;
+            }
+            let histogram = types
+                .iter()
+                .map(|(json_type, count)| format!("{} {}", json_type, count))
+                .collect::<Vec<String>>()
+                .join(", ");
+            let mixed = if types.len() > 1 { "\tMIXED" } else { "" };
+            println!("col_{:05}_{}\t{}{}", idx, profile.column_names[idx], histogram, mixed);
+        }
+    }
+
+    println!();
+    if !profile.grains.is_empty() {
+        println!("Grain: {}", info.first_grain());
+    }
+    println!(
+        "{:<32}\t{:<8}\t{:<8}\t{:<32}",
+        "column", "count", "pattern", "example"
+    );
+    println!("{:-<32}\t{:-<8}\t{:-<8}\t{:-<32}", "", "", "", "");
+
+    for &idx in columns {
+        let name = &profile.column_names[idx];
+        for (value, count) in sorted_masks(profile, idx) {
+            let empty_string = "".to_string();
+            let example = profile.example_maps[idx].get(value).unwrap_or(&empty_string);
+            // keep multi-line csv values on one report line
+            let example = example.replace('\r', "\\r").replace('\n', "\\n").replace('\t', "\\t");
+
+            println!(
+                "col_{:05}_{}\t{:<8}\t{:<8}\t{:<32}",
+                idx, name, count, value, example
+            );
+        }
+    }
+
+    for grain in 0..profile.grains.len() {
+        println!();
+        println!("Grain: {}", profile.grains[grain].grain);
+        println!("{:<32}\t{:<8}\t{:<8}\t{:<32}", "column", "count", "pattern", "example");
+        println!("{:-<32}\t{:-<8}\t{:-<8}\t{:-<32}", "", "", "", "");
+        for &idx in columns {
+            for mask in grain_masks(profile, grain, idx) {
+                print_text_mask(profile, idx, None, &mask, 0);
+            }
+        }
+    }
+
+    // every mask of the coarser grain, followed by the masks of the finer grain under it
+    for grain in 0..profile.grains.len() {
+        println!();
+        println!("DrillDown:");
+        println!("{:<32}\t{:<8}\t{:<8}\t{:<8}\t{:<32}", "column", "grain", "count", "pattern", "example");
+        println!("{:-<32}\t{:-<8}\t{:-<8}\t{:-<8}\t{:-<32}", "", "", "", "", "");
+        for &idx in columns {
+            let (first_is_coarser, nested) = drill_down(profile, grain, idx);
+            let (parent, child) = drill_down_grains(profile, info, grain, first_is_coarser);
+            for mask in nested {
+                print_text_mask(profile, idx, Some(parent), &mask, 0);
+                for child_mask in &mask.children {
+                    print_text_mask(profile, idx, Some(child), child_mask, 1);
+                }
+            }
+        }
+    }
+
+    // the masks that do not conform are listed after the share, most frequent first
+    if let Some(classifier) = classifier {
+        println!();
+        println!("SemanticTypes:");
+        println!("{:<32}\t{:<12}\t{:<8}\t{:<32}", "column", "type", "conform", "nonconforming masks");
+        println!("{:-<32}\t{:-<12}\t{:-<8}\t{:-<32}", "", "", "", "");
+        for &idx in columns {
+            let inference = match classifier.infer(profile, idx) {
+                Some(inference) => inference,
+                None => continue,
+            };
+            let mut nonconforming = inference
+                .nonconforming
+                .iter()
+                .take(SEMANTIC_TEXT_MASKS)
+                .map(|(mask, count, _)| format!("{} ({})", mask, count))
+                .collect::<Vec<String>>();
+            if inference.nonconforming.len() > SEMANTIC_TEXT_MASKS {
+                nonconforming.push(format!("... {} more", inference.nonconforming.len() - SEMANTIC_TEXT_MASKS));
+            }
+            println!(
+                "col_{:05}_{}\t{:<12}\t{:<8}\t{}",
+                idx,
+                profile.column_names[idx],
+                inference.semantic_type,
+                format!("{:.2}%", inference.share() * 100.0),
+                nonconforming.join(", ")
+            );
+        }
+    }
+}
+
+// A report line of a mask, indented by its level in a drill-down.
+fn print_text_mask(profile: &Profile, idx: usize, grain: Option<&str>, mask: &NestedMask, level: usize) {
+    let indent = "  ".repeat(level);
+    let example = mask.example.unwrap_or("").replace('\r', "\\r").replace('\n', "\\n").replace('\t', "\\t");
+    let grain = grain.map_or(String::new(), |grain| format!("{:<8}\t", format!("{}{}", indent, grain)));
+    println!(
+        "col_{:05}_{}\t{}{:<8}\t{:<8}\t{:<32}",
+        idx,
+        profile.column_names[idx],
+        grain,
+        format!("{}{}", indent, mask.count),
+        format!("{}{}", indent, mask.mask),
+        example
+    );
+}
@@ -0,0 +1,324 @@
+use std::fs;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::profile::Profile;
+
+// Exit status used when any data quality assertion fails.
+pub const RULES_EXIT_CODE: i32 = 4;
+
+// A data quality assertions file, e.g.
+//
+//   [fields_per_line]
+//   expected = 12
+//
+//   [[column]]
+//   name = "RegAddress.PostCode"
+//   allowed_masks = ["A9 9A", "A9A 9A"]
+//   max_empty_share = 0.05
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleSet {
+    pub fields_per_line: Option<FieldsPerLineRule>,
+    #[serde(default, rename = "column")]
+    pub columns: Vec<ColumnRule>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldsPerLineRule {
+    pub expected: usize,
+    // share of rows that must have the expected field count, every row by default
+    pub min_share: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColumnRule {
+    pub name: String,
+    #[serde(default = "default_required")]
+    pub required: bool,
+    pub allowed_masks: Option<Vec<String>>,
+    #[serde(default)]
+    pub forbidden_masks: Vec<String>,
+    pub min_dominant_share: Option<f64>,
+    pub max_empty_share: Option<f64>,
+}
+
+fn default_required() -> bool {
+    true
+}
+
+pub struct RuleResult {
+    pub column: String,
+    pub rule: &'static str,
+    pub passed: bool,
+    pub detail: String,
+}
+
+// Masks produced by empty values: '_' for the low grains, '' for the high grains.
+fn is_empty_mask(mask: &str) -> bool {
+    mask.is_empty() || mask == "_"
+}
+
+impl RuleSet {
+    pub fn load(path: &str) -> Result<RuleSet, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("unable to read rules file {}: {}", path, e))?;
+        toml::from_str(&text).map_err(|e| format!("invalid rules file {}: {}", path, e))
+    }
+
+    pub fn evaluate(&self, profile: &Profile) -> Vec<RuleResult> {
+        let mut results = Vec::new();
+
+        if let Some(rule) = &self.fields_per_line {
+            let total = profile.field_count_map.values().sum::<usize>();
+            let matching = profile.field_count_map.get(&rule.expected).copied().unwrap_or(0);
+            let share = matching as f64 / total.max(1) as f64;
+            let min_share = rule.min_share.unwrap_or(1.0);
+            results.push(RuleResult {
+                column: "FieldsPerLine".to_string(),
+                rule: "expected",
+                passed: share >= min_share,
+                detail: format!("{} of {} rows have {} fields ({:.4}, minimum {})", matching, total, rule.expected, share, min_share),
+            });
+        }
+
+        for rule in &self.columns {
+            let idx = match profile.column_index(&rule.name) {
+                Some(idx) => idx,
+                None => {
+                    results.push(RuleResult {
+                        column: rule.name.clone(),
+                        rule: "required",
+                        passed: !rule.required,
+                        detail: "column not found".to_string(),
+                    });
+                    continue;
+                }
+            };
+            let frequency_map = &profile.frequency_maps[idx];
+            let total = frequency_map.values().sum::<usize>().max(1) as f64;
+
+            if let Some(allowed) = &rule.allowed_masks {
+                let mut unexpected = frequency_map
+                    .iter()
+                    .filter(|(mask, _)| !allowed.contains(mask))
+                    .map(|(mask, count)| format!("'{}' ({})", mask, count))
+                    .collect::<Vec<String>>();
+                unexpected.sort_unstable();
+                results.push(RuleResult {
+                    column: rule.name.clone(),
+                    rule: "allowed_masks",
+                    passed: unexpected.is_empty(),
+                    detail: if unexpected.is_empty() {
+                        "all masks allowed".to_string()
+                    } else {
+                        format!("unexpected masks: {}", unexpected.join(", "))
+                    },
+                });
+            }
+
+            if !rule.forbidden_masks.is_empty() {
+                let mut found = rule
+                    .forbidden_masks
+                    .iter()
+                    .filter_map(|mask| frequency_map.get(mask).map(|count| format!("'{}' ({})", mask, count)))
+                    .collect::<Vec<String>>();
+                found.sort_unstable();
+                results.push(RuleResult {
+                    column: rule.name.clone(),
+                    rule: "forbidden_masks",
+                    passed: found.is_empty(),
+                    detail: if found.is_empty() {
+                        "no forbidden masks".to_string()
+                    } else {
+                        format!("forbidden masks present: {}", found.join(", "))
+                    },
+                });
+            }
+
+            if let Some(min_share) = rule.min_dominant_share {
+                let dominant = frequency_map.iter().max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)));
+                let (mask, share) = dominant.map_or(("", 0.0), |(mask, count)| (mask.as_str(), *count as f64 / total));
+                results.push(RuleResult {
+                    column: rule.name.clone(),
+                    rule: "min_dominant_share",
+                    passed: share >= min_share,
+                    detail: format!("dominant mask '{}' has share {:.4}, minimum {}", mask, share, min_share),
+                });
+            }
+
+            if let Some(max_share) = rule.max_empty_share {
+                let empty = frequency_map
+                    .iter()
+                    .filter(|(mask, _)| is_empty_mask(mask))
+                    .map(|(_, count)| count)
+                    .sum::<usize>();
+                let share = empty as f64 / total;
+                results.push(RuleResult {
+                    column: rule.name.clone(),
+                    rule: "max_empty_share",
+                    passed: share <= max_share,
+                    detail: format!("{} empty values, share {:.4}, maximum {}", empty, share, max_share),
+                });
+            }
+        }
+
+        results
+    }
+}
+
+pub fn print_rule_results(results: &[RuleResult], output_format: &str) {
+    let failures = results.iter().filter(|result| !result.passed).count();
+
+    if output_format == "json" {
+        let report = json!({
+            "passed": failures == 0,
+            "failures": failures,
+            "results": results
+                .iter()
+                .map(|result| json!({
+                    "column": result.column,
+                    "rule": result.rule,
+                    "passed": result.passed,
+                    "detail": result.detail,
+                }))
+                .collect::<Vec<Value>>(),
+        });
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        return;
+    }
+
+    println!();
+    println!("Data Quality Assertions: {}", if failures == 0 { "PASS" } else { "FAIL" });
+    println!("Failed rules: {} of {}", failures, results.len());
+    println!();
+    println!("{:<32}\t{:<20}\t{:<6}\tdetail", "column", "rule", "result");
+    println!("{:-<32}\t{:-<20}\t{:-<6}\t{:-<32}", "", "", "", "");
+    for result in results {
+        println!(
+            "{:<32}\t{:<20}\t{:<6}\t{}",
+            result.column,
+            result.rule,
+            if result.passed { "PASS" } else { "FAIL" },
+            result.detail
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profiler::{Profiler, ProfilerOptions};
+
+    fn profile(lines: &[&str]) -> Profile {
+        let mut profiler = Profiler::new(ProfilerOptions::default()).unwrap();
+        for line in lines {
+            profiler.feed_line(line).unwrap();
+        }
+        profiler.finish()
+    }
+
+    fn postcodes() -> Profile {
+        profile(&["id|postcode|note", "1|SW1A 1AA|x", "2|N1 9GU|", "3|EC1A 1BB|y", "4|W1 1AA|"])
+    }
+
+    // (rule, passed, detail) of each result, for a rules file that must parse
+    fn evaluate(rules: &str, profile: &Profile) -> Vec<(&'static str, bool, String)> {
+        let rules: RuleSet = toml::from_str(rules).unwrap();
+        rules.evaluate(profile).into_iter().map(|result| (result.rule, result.passed, result.detail)).collect()
+    }
+
+    #[test]
+    fn mask_lists_are_checked() {
+        let profile = postcodes();
+        let rules = r#"
+            [[column]]
+            name = "postcode"
+            allowed_masks = ["A9A 9A", "A9 9A"]
+            forbidden_masks = ["9"]
+
+            [[column]]
+            name = "postcode"
+            allowed_masks = ["A9A 9A"]
+            forbidden_masks = ["A9 9A", "9"]
+        "#;
+        assert_eq!(
+            evaluate(rules, &profile),
+            [
+                ("allowed_masks", true, "all masks allowed".to_string()),
+                ("forbidden_masks", true, "no forbidden masks".to_string()),
+                ("allowed_masks", false, "unexpected masks: 'A9 9A' (2)".to_string()),
+                ("forbidden_masks", false, "forbidden masks present: 'A9 9A' (2)".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn shares_are_checked_against_their_limits() {
+        let profile = postcodes();
+        let results = evaluate(
+            r#"
+            [[column]]
+            name = "postcode"
+            min_dominant_share = 0.5
+
+            [[column]]
+            name = "postcode"
+            min_dominant_share = 0.6
+
+            [[column]]
+            name = "note"
+            max_empty_share = 0.5
+
+            [[column]]
+            name = "note"
+            max_empty_share = 0.25
+            "#,
+            &profile,
+        );
+        assert_eq!(
+            results.iter().map(|(rule, passed, _)| (*rule, *passed)).collect::<Vec<_>>(),
+            [
+                ("min_dominant_share", true),
+                ("min_dominant_share", false),
+                ("max_empty_share", true),
+                ("max_empty_share", false)
+            ]
+        );
+        // equal counts go to the first mask in order
+        assert_eq!(results[0].2, "dominant mask 'A9 9A' has share 0.5000, minimum 0.5");
+        assert_eq!(results[3].2, "2 empty values, share 0.5000, maximum 0.25");
+    }
+
+    #[test]
+    fn field_counts_are_checked() {
+        let profile = profile(&["id|name", "1|a", "2|b", "3|c", "4|d|extra"]);
+        assert_eq!(
+            evaluate("[fields_per_line]\nexpected = 2", &profile),
+            [("expected", false, "3 of 4 rows have 2 fields (0.7500, minimum 1)".to_string())]
+        );
+        assert!(evaluate("[fields_per_line]\nexpected = 2\nmin_share = 0.75", &profile)[0].1);
+    }
+
+    #[test]
+    fn missing_columns_fail_unless_optional() {
+        let profile = postcodes();
+        assert_eq!(
+            evaluate("[[column]]\nname = \"email\"\n\n[[column]]\nname = \"phone\"\nrequired = false", &profile),
+            [("required", false, "column not found".to_string()), ("required", true, "column not found".to_string())]
+        );
+    }
+
+    #[test]
+    fn misspelt_settings_are_rejected() {
+        for rules in [
+            "[[column]]\nname = \"postcode\"\nallowed_mask = [\"A9 9A\"]",
+            "[fields_per_lines]\nexpected = 2",
+            "[fields_per_line]\nexpected = 2\nminimum_share = 0.9",
+        ] {
+            assert!(toml::from_str::<RuleSet>(rules).is_err(), "{}", rules);
+        }
+    }
+}
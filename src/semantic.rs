@@ -0,0 +1,308 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use chrono::NaiveDate;
+use regex::Regex;
+
+use crate::profile::Profile;
+
+// Share of a column's values that must conform to a type for the column to be given it.
+pub const MIN_SHARE: f64 = 0.5;
+
+// An amount of money, with or without thousands separators and pence / cents.
+const AMOUNT: &str = r"(?:[0-9]{1,3}(?:,[0-9]{3})+|[0-9]+)(?:\.[0-9]{1,2})?";
+const CURRENCY_CODE: &str = "(?:GBP|USD|EUR|JPY|CHF)";
+
+// Pairs of letters that are never the prefix of a National Insurance number.
+const NINO_EXCLUDED_PREFIXES: [&str; 7] = ["BG", "GB", "KN", "NK", "NT", "TN", "ZZ"];
+
+// A semantic type: a value conforms when it matches the pattern and passes the check, which
+// catches what a pattern cannot (check digits, calendar dates, address ranges).
+struct SemanticType {
+    name: &'static str,
+    pattern: Regex,
+    check: Check,
+}
+
+type Check = fn(&str) -> bool;
+
+// What a column's values were recognised as.
+pub struct Inference<'a> {
+    pub semantic_type: &'static str,
+    pub conforming: usize,
+    pub total: usize,
+    // masks with values that do not conform, by descending number of those values:
+    // (mask, nonconforming values, example of the mask)
+    pub nonconforming: Vec<(&'a str, usize, Option<&'a str>)>,
+}
+
+impl Inference<'_> {
+    pub fn share(&self) -> f64 {
+        self.conforming as f64 / self.total as f64
+    }
+}
+
+// Maps the masks of a column to the semantic types below. Types earlier in the list win ties.
+pub struct Classifier {
+    types: Vec<SemanticType>,
+}
+
+fn any(_: &str) -> bool {
+    true
+}
+
+fn is_iso_date(value: &str) -> bool {
+    value.get(..10).is_some_and(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok())
+}
+
+// A UK number has 9 or 10 digits after the 0 trunk prefix or the +44 country code.
+fn is_uk_phone(value: &str) -> bool {
+    let digits = value.chars().filter(|c| c.is_ascii_digit()).collect::<String>();
+    let national = if value.starts_with('+') || value.starts_with("00") {
+        let national = digits.trim_start_matches('0').strip_prefix("44").unwrap_or("");
+        // +44 (0)20 ... keeps the trunk prefix in brackets
+        if value.contains("(0)") {
+            national.strip_prefix('0').unwrap_or("")
+        } else {
+            national
+        }
+    } else {
+        digits.strip_prefix('0').unwrap_or("")
+    };
+    (9..=10).contains(&national.len())
+}
+
+fn is_nino(value: &str) -> bool {
+    match value.get(..2) {
+        Some(prefix) => !NINO_EXCLUDED_PREFIXES.contains(&prefix.to_ascii_uppercase().as_str()),
+        None => false,
+    }
+}
+
+// ISO 13616 check: the country code and check digits are moved to the end, letters become
+// 10 - 35, and the resulting number must leave a remainder of 1 when divided by 97.
+fn is_iban(value: &str) -> bool {
+    let compact = value.chars().filter(|c| *c != ' ').collect::<String>();
+    if !(15..=34).contains(&compact.len()) {
+        return false;
+    }
+    let (head, tail) = match (compact.get(..4), compact.get(4..)) {
+        (Some(head), Some(tail)) => (head, tail),
+        _ => return false,
+    };
+    let mut remainder = 0;
+    for c in tail.chars().chain(head.chars()) {
+        let digit = match c.to_digit(36) {
+            Some(digit) => digit,
+            None => return false,
+        };
+        let scale = if digit < 10 { 10 } else { 100 };
+        remainder = (remainder * scale + digit) % 97;
+    }
+    remainder == 1
+}
+
+fn is_ipv4(value: &str) -> bool {
+    value.parse::<Ipv4Addr>().is_ok()
+}
+
+fn is_ipv6(value: &str) -> bool {
+    value.parse::<Ipv6Addr>().is_ok()
+}
+
+impl Classifier {
+    pub fn new() -> Classifier {
+        let currency = format!(
+            r"^[-+]?(?:[£$€¥](?-u:\s)?{amount}|{code}(?-u:\s)?{amount}|{amount}(?-u:\s)?{code}|(?:[0-9]{{1,3}}(?:,[0-9]{{3}})+|[0-9]+)\.[0-9]{{2}})$",
+            amount = AMOUNT,
+            code = CURRENCY_CODE,
+        );
+        // The patterns match ASCII only ((?-u), [0-9]): Unicode classes let through digits and
+        // letters such as the Kelvin sign that the checks cannot read.
+        let types: [(&'static str, &str, Check); 11] = [
+            ("uk_postcode", r"(?i-u)^(?:GIR ?0AA|[A-Z]{1,2}\d[A-Z\d]? ?\d[A-Z]{2})$", any),
+            ("nino", r"(?i-u)^[A-CEGHJ-PR-TW-Z][A-CEGHJ-NPR-TW-Z] ?\d{2} ?\d{2} ?\d{2} ?[A-D]$", is_nino),
+            ("iban", r"(?-u)^[A-Z]{2}\d{2}(?: ?[A-Z\d]){11,30}$", is_iban),
+            ("email", r"(?-u)^[A-Za-z\d.!#$%&'*+/=?^_`{|}~-]+@[A-Za-z\d-]+(?:\.[A-Za-z\d-]+)*\.[A-Za-z]{2,}$", any),
+            ("url", r"^(?i-u:https?|ftp)://[^\s/?#:]+(?::[0-9]+)?(?:[/?#]\S*)?$", any),
+            ("uuid", r"(?-u)^[\da-fA-F]{8}-[\da-fA-F]{4}-[\da-fA-F]{4}-[\da-fA-F]{4}-[\da-fA-F]{12}$", any),
+            (
+                "iso_date",
+                r"(?-u)^\d{4}-\d{2}-\d{2}(?:[T ]\d{2}:\d{2}(?::\d{2}(?:\.\d+)?)?(?:Z|[+-]\d{2}:?\d{2})?)?$",
+                is_iso_date,
+            ),
+            ("ipv4", r"(?-u)^\d{1,3}(?:\.\d{1,3}){3}$", is_ipv4),
+            ("ipv6", r"(?-u)^[\da-fA-F:.]*:[\da-fA-F:.]*$", is_ipv6),
+            ("uk_phone", r"(?-u)^(?:(?:\+|00)44\s?(?:\(0\)\s?)?|\(?0)\d[\d\s)-]{7,14}\d$", is_uk_phone),
+            ("currency", &currency, any),
+        ];
+        Classifier {
+            types: types
+                .iter()
+                .map(|&(name, pattern, check)| SemanticType { name, pattern: Regex::new(pattern).unwrap(), check })
+                .collect(),
+        }
+    }
+
+    // Names of the types a value conforms to.
+    pub fn classify<'a>(&'a self, value: &'a str) -> impl Iterator<Item = &'static str> + 'a {
+        let value = value.trim();
+        self.types
+            .iter()
+            .filter(move |semantic_type| semantic_type.pattern.is_match(value) && (semantic_type.check)(value))
+            .map(|semantic_type| semantic_type.name)
+    }
+
+    // The type most of a column's values conform to, if at least MIN_SHARE of them do. It
+    // is read from the counts of conforming values of each mask, which the profile keeps
+    // when it is made with a classifier.
+    pub fn infer<'a>(&self, profile: &'a Profile, idx: usize) -> Option<Inference<'a>> {
+        let semantic_map = &profile.semantic_maps[idx];
+        let conforming_values = |mask: &str, semantic_type: &str| {
+            semantic_map
+                .get(mask)
+                .and_then(|counts| counts.get(semantic_type))
+                .copied()
+                .unwrap_or(0)
+        };
+        let total = profile.frequency_maps[idx].values().sum::<usize>();
+
+        let (semantic_type, conforming) = self
+            .types
+            .iter()
+            .map(|semantic_type| {
+                let conforming = semantic_map
+                    .values()
+                    .filter_map(|counts| counts.get(semantic_type.name))
+                    .sum::<usize>();
+                (semantic_type.name, conforming)
+            })
+            .fold(None, |best: Option<(&'static str, usize)>, candidate| match best {
+                Some(best) if best.1 >= candidate.1 => Some(best),
+                _ => Some(candidate),
+            })?;
+        if total == 0 || (conforming as f64) < MIN_SHARE * total as f64 {
+            return None;
+        }
+
+        let mut nonconforming = profile.frequency_maps[idx]
+            .iter()
+            .map(|(mask, count)| (mask.as_str(), count - conforming_values(mask, semantic_type)))
+            .filter(|(_, count)| *count > 0)
+            .map(|(mask, count)| (mask, count, profile.example_maps[idx].get(mask).map(|example| example.as_str())))
+            .collect::<Vec<_>>();
+        nonconforming.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        Some(Inference { semantic_type, conforming, total, nonconforming })
+    }
+}
+
+impl Default for Classifier {
+    fn default() -> Self {
+        Classifier::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::InputLine;
+    use crate::profiler::{Profiler, ProfilerOptions};
+
+    fn types_of(value: &str) -> Vec<&'static str> {
+        Classifier::new().classify(value).collect()
+    }
+
+    #[test]
+    fn values_are_recognised() {
+        let classifier = Classifier::new();
+        for (value, expected) in [
+            ("SW1A 1AA", "uk_postcode"),
+            ("ec1a1bb", "uk_postcode"),
+            ("AB 12 34 56 C", "nino"),
+            ("GB82 WEST 1234 5698 7654 32", "iban"),
+            ("jo.bloggs+news@example.co.uk", "email"),
+            ("https://example.com:8080/path?q=1", "url"),
+            ("123e4567-e89b-12d3-a456-426614174000", "uuid"),
+            ("2024-02-29", "iso_date"),
+            ("2024-02-29T13:45:00.123Z", "iso_date"),
+            ("192.168.0.1", "ipv4"),
+            ("2001:db8::8a2e:370:7334", "ipv6"),
+            ("020 7946 0958", "uk_phone"),
+            ("+44 (0)20 7946 0958", "uk_phone"),
+            ("07700 900123", "uk_phone"),
+            ("£1,234.50", "currency"),
+            ("12.99", "currency"),
+            ("EUR 40", "currency"),
+        ] {
+            assert_eq!(classifier.classify(value).collect::<Vec<&str>>(), [expected], "{}", value);
+        }
+    }
+
+    #[test]
+    fn lookalikes_are_not_recognised() {
+        for value in [
+            "SW1A 1A",
+            "GB12 3456 7890",
+            "BG 12 34 56 C",
+            "GB82 WEST 1234 5698 7654 33",
+            "jo.bloggs@example",
+            "www.example.com",
+            "123e4567-e89b-12d3-a456-42661417400",
+            "2023-02-29",
+            "256.1.1.1",
+            "12:30",
+            "020 7946 09",
+            "1,23.50",
+            "12.5",
+            "\u{212A}A 12 34 56 C",
+            "2024-02-2\u{0669}",
+            "GB82 WEST 1234 5698 7654 \u{0663}2",
+        ] {
+            assert!(types_of(value).is_empty(), "{}: {:?}", value, types_of(value));
+        }
+    }
+
+    fn semantic_profiler(chunk_size: usize) -> Profiler {
+        Profiler::new(ProfilerOptions { grain: "H".to_string(), semantic: true, chunk_size, ..Default::default() })
+            .unwrap()
+    }
+
+    #[test]
+    fn column_type_with_conforming_share() {
+        let mut profiler = semantic_profiler(10000);
+        for line in ["id|postcode", "1|SW1A 1AA", "2|N1 9GU", "3|EC1A 1BB", "4|unknown", "5|N1 9GU"] {
+            profiler.feed_line(line).unwrap();
+        }
+        let profile = profiler.finish();
+        let classifier = Classifier::new();
+
+        let postcode = classifier.infer(&profile, profile.column_index("postcode").unwrap()).unwrap();
+        assert_eq!(postcode.semantic_type, "uk_postcode");
+        assert_eq!((postcode.conforming, postcode.total), (4, 5));
+        assert_eq!(postcode.share(), 0.8);
+        assert_eq!(postcode.nonconforming, [("aaaaaaa", 1, Some("unknown"))]);
+
+        assert!(classifier.infer(&profile, profile.column_index("id").unwrap()).is_none());
+    }
+
+    #[test]
+    fn every_value_of_a_mask_is_checked() {
+        // all the dates share the mask 9999-99-99, but a quarter of them are not real dates
+        let mut lines = vec![InputLine { number: 1, offset: 0, text: "joined".to_string() }];
+        for n in 0..400 {
+            let date = if n % 4 == 0 { "2024-13-01" } else { "2024-02-29" };
+            lines.push(InputLine { number: n + 2, offset: 0, text: date.to_string() });
+        }
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+        for _ in 0..5 {
+            let mut profiler = semantic_profiler(7);
+            pool.install(|| profiler.feed_lines(&lines));
+            let profile = profiler.finish();
+
+            let joined = Classifier::new().infer(&profile, 0).unwrap();
+            assert_eq!(joined.semantic_type, "iso_date");
+            assert_eq!((joined.conforming, joined.total), (300, 400));
+            assert_eq!(joined.nonconforming.len(), 1);
+            assert_eq!((joined.nonconforming[0].0, joined.nonconforming[0].1), ("9999-99-99", 100));
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::input::InputSource;
+use crate::profile::{GrainMaps, ParseError, Profile};
+use crate::report::RunInfo;
+
+// Version 2 added the masks of the further grains of multi-grain runs, version 1
+// snapshots are still read.
+const SNAPSHOT_VERSION: u32 = 2;
+
+// A serializable dump of an in-memory Profile. Snapshots taken on different machines or
+// partitions can be merged into a single report; the mask counts double as the sampling
+// weights of the examples, so merging keeps the example selection uniformly random.
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub grain: String,
+    pub delimiter: String,
+    pub format: String,
+    pub record_count: usize,
+    #[serde(default)]
+    pub parse_error_count: usize,
+    #[serde(default)]
+    pub parse_errors: Vec<SnapshotParseError>,
+    #[serde(default)]
+    pub encoding_error_count: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub encoding_errors: Vec<SnapshotParseError>,
+    pub header_len: usize,
+    pub input_files: Vec<SnapshotInput>,
+    pub fields_per_line: Vec<SnapshotFieldCount>,
+    pub columns: Vec<SnapshotColumn>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SnapshotInput {
+    pub file: String,
+    pub rows: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SnapshotParseError {
+    pub file: String,
+    pub line: usize,
+    pub offset: u64,
+    pub message: String,
+}
+
+impl SnapshotParseError {
+    fn from_error(error: &ParseError) -> Self {
+        SnapshotParseError {
+            file: error.file.clone(),
+            line: error.line,
+            offset: error.offset,
+            message: error.message.clone(),
+        }
+    }
+
+    fn into_error(self) -> ParseError {
+        ParseError {
+            file: self.file,
+            line: self.line,
+            offset: self.offset,
+            message: self.message,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SnapshotFieldCount {
+    pub fields: usize,
+    pub rows: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SnapshotColumn {
+    pub name: String,
+    pub masks: Vec<SnapshotMask>,
+    // JSON value type histogram, absent for tabular columns
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub types: Vec<SnapshotType>,
+    // masks at the further grains of a multi-grain run, in grain order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub grains: Vec<SnapshotGrain>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SnapshotGrain {
+    pub grain: String,
+    pub masks: Vec<SnapshotGrainMask>,
+}
+
+// A mask at a further grain, counted with the first grain mask of the same values.
+#[derive(Serialize, Deserialize)]
+pub struct SnapshotGrainMask {
+    pub first_mask: String,
+    pub mask: String,
+    pub count: usize,
+    pub example: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SnapshotType {
+    pub json_type: String,
+    pub count: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SnapshotMask {
+    pub mask: String,
+    pub count: usize,
+    pub example: Option<String>,
+}
+
+impl Snapshot {
+    pub fn from_profile(profile: &Profile, info: &RunInfo) -> Snapshot {
+        let mut fields_per_line = profile
+            .field_count_map
+            .iter()
+            .map(|(fields, rows)| SnapshotFieldCount { fields: *fields, rows: *rows })
+            .collect::<Vec<SnapshotFieldCount>>();
+        fields_per_line.sort_unstable_by_key(|field_count| field_count.fields);
+
+        let columns = profile
+            .column_names
+            .iter()
+            .enumerate()
+            .map(|(idx, name)| {
+                let mut masks = profile.frequency_maps[idx]
+                    .iter()
+                    .map(|(mask, count)| SnapshotMask {
+                        mask: mask.clone(),
+                        count: *count,
+                        example: profile.example_maps[idx].get(mask).cloned(),
+                    })
+                    .collect::<Vec<SnapshotMask>>();
+                masks.sort_unstable_by(|a, b| b.count.cmp(&a.count).then_with(|| a.mask.cmp(&b.mask)));
+                let mut types = profile.type_maps[idx]
+                    .iter()
+                    .map(|(json_type, count)| SnapshotType { json_type: json_type.clone(), count: *count })
+                    .collect::<Vec<SnapshotType>>();
+                types.sort_unstable_by(|a, b| b.count.cmp(&a.count).then_with(|| a.json_type.cmp(&b.json_type)));
+                let grains = profile
+                    .grains
+                    .iter()
+                    .map(|grain| {
+                        let mut masks = grain.frequency_maps[idx]
+                            .iter()
+                            .map(|(pair, count)| SnapshotGrainMask {
+                                first_mask: pair.0.clone(),
+                                mask: pair.1.clone(),
+                                count: *count,
+                                example: grain.example_maps[idx].get(pair).cloned(),
+                            })
+                            .collect::<Vec<SnapshotGrainMask>>();
+                        masks.sort_unstable_by(|a, b| {
+                            b.count.cmp(&a.count).then_with(|| (&a.first_mask, &a.mask).cmp(&(&b.first_mask, &b.mask)))
+                        });
+                        SnapshotGrain { grain: grain.grain.clone(), masks }
+                    })
+                    .collect();
+                SnapshotColumn { name: name.clone(), masks, types, grains }
+            })
+            .collect();
+
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            grain: info.grain.clone(),
+            delimiter: info.delimiter.clone(),
+            format: info.format.clone(),
+            record_count: profile.record_count,
+            parse_error_count: profile.parse_error_count,
+            parse_errors: profile.parse_errors.iter().map(SnapshotParseError::from_error).collect(),
+            encoding_error_count: profile.encoding_error_count,
+            encoding_errors: profile.encoding_errors.iter().map(SnapshotParseError::from_error).collect(),
+            header_len: profile.header_len,
+            input_files: profile
+                .input_files
+                .iter()
+                .map(|(file, rows)| SnapshotInput { file: file.clone(), rows: *rows })
+                .collect(),
+            fields_per_line,
+            columns,
+        }
+    }
+
+    pub fn into_profile(self) -> (Profile, RunInfo) {
+        let mut profile = Profile::new();
+        profile.grains = self.grain.split(',').skip(1).map(|grain| GrainMaps::new(grain.trim())).collect();
+        for column in self.columns {
+            let idx = profile.column(&column.name);
+            for mask in column.masks {
+                if let Some(example) = mask.example {
+                    profile.example_maps[idx].insert(mask.mask.clone(), example);
+                }
+                profile.frequency_maps[idx].insert(mask.mask, mask.count);
+            }
+            for json_type in column.types {
+                profile.type_maps[idx].insert(json_type.json_type, json_type.count);
+            }
+            for snapshot_grain in column.grains {
+                let grain = match profile.grains.iter_mut().find(|grain| grain.grain == snapshot_grain.grain) {
+                    Some(grain) => grain,
+                    None => continue,
+                };
+                for mask in snapshot_grain.masks {
+                    let pair = (mask.first_mask, mask.mask);
+                    if let Some(example) = mask.example {
+                        grain.example_maps[idx].insert(pair.clone(), example);
+                    }
+                    grain.frequency_maps[idx].insert(pair, mask.count);
+                }
+            }
+        }
+        for field_count in self.fields_per_line {
+            profile.field_count_map.insert(field_count.fields, field_count.rows);
+        }
+        profile.input_files = self.input_files.into_iter().map(|input| (input.file, input.rows)).collect();
+        profile.record_count = self.record_count;
+        profile.parse_error_count = self.parse_error_count;
+        profile.parse_errors = self.parse_errors.into_iter().map(SnapshotParseError::into_error).collect();
+        profile.encoding_error_count = self.encoding_error_count;
+        profile.encoding_errors = self.encoding_errors.into_iter().map(SnapshotParseError::into_error).collect();
+        profile.header_len = self.header_len;
+
+        let info = RunInfo {
+            grain: self.grain,
+            delimiter: self.delimiter,
+            format: self.format,
+            detected: None,
+        };
+        (profile, info)
+    }
+
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()
+    }
+
+    // Snapshots are read through the normal input layer, so compressed snapshots work too.
+    pub fn read(source: &InputSource) -> io::Result<Snapshot> {
+        let snapshot: Snapshot = serde_json::from_reader(source.open()?)?;
+        if snapshot.version == 0 || snapshot.version > SNAPSHOT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported snapshot version {} in {}", snapshot.version, source),
+            ));
+        }
+        Ok(snapshot)
+    }
+}
+
+// Combines snapshots into a single profile. All snapshots must use the same grain,
+// otherwise their masks are not comparable.
+pub fn merge_snapshots(sources: &[InputSource]) -> Result<(Profile, RunInfo), String> {
+    let mut merged: Option<(Profile, RunInfo)> = None;
+    for source in sources {
+        let snapshot = Snapshot::read(source).map_err(|e| format!("unable to read snapshot {}: {}", source, e))?;
+        let (profile, info) = snapshot.into_profile();
+        merged = match merged {
+            None => Some((profile, info)),
+            Some((mut merged_profile, merged_info)) => {
+                if merged_info.grain != info.grain {
+                    return Err(format!(
+                        "snapshot {} uses grain '{}' but previous snapshots use '{}'",
+                        source, info.grain, merged_info.grain
+                    ));
+                }
+                merged_profile.merge(profile);
+                Some((merged_profile, merged_info))
+            }
+        };
+    }
+    merged.ok_or_else(|| "no snapshots to merge".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profiler::{Profiler, ProfilerOptions};
+
+    #[test]
+    fn further_grains_survive_a_snapshot() {
+        let mut profiler = Profiler::new(ProfilerOptions { grain: "LU,H".to_string(), ..Default::default() }).unwrap();
+        for line in ["postcode", "SW1A 1AA", "N1 9GU", "EC1A 1BB"] {
+            profiler.feed_line(line).unwrap();
+        }
+        let profile = profiler.finish();
+        let info = RunInfo { grain: "LU,H".to_string(), delimiter: "|".to_string(), format: "tabular".to_string(), detected: None };
+
+        let json = serde_json::to_string(&Snapshot::from_profile(&profile, &info)).unwrap();
+        let (restored, restored_info) = serde_json::from_str::<Snapshot>(&json).unwrap().into_profile();
+        assert_eq!(restored_info.grain, "LU,H");
+        assert_eq!(restored.grains.len(), 1);
+        assert_eq!(restored.grains[0].grain, "H");
+        assert_eq!(restored.grains[0].frequency_maps[0], profile.grains[0].frequency_maps[0]);
+        assert_eq!(restored.grains[0].example_maps[0], profile.grains[0].example_maps[0]);
+        assert_eq!(restored.frequency_maps[0], profile.frequency_maps[0]);
+    }
+}
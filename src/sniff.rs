@@ -0,0 +1,370 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, BufReader, Cursor, Read};
+
+use crate::input::read_record;
+use crate::mask::{LowGrainUnicode, Mask};
+
+// Number of lines inspected when guessing the format of an input.
+const SNIFF_LINES: usize = 100;
+// Cap on the sample size, for inputs with very long lines (or none at all).
+const SNIFF_BYTES: usize = 1 << 20;
+
+// Delimiters that are tried, multi-character ones before the single characters they contain.
+const CANDIDATES: [&str; 9] = ["|~|", "~|~", "^|^", "||", "|", ",", "\t", ";", "^"];
+
+// Share of the sampled lines that must have the same number of delimiters.
+const MIN_CONSISTENCY: f64 = 0.9;
+
+// Share of a column's values below the first row that must have the same (low grain) mask
+// for a first row value with a mask none of them have to stand out as a column name.
+const HEADER_MASK_SHARE: f64 = 0.5;
+
+// What the first lines of an input look like.
+pub struct Sniffed {
+    pub format: &'static str,
+    // None for JSON and Parquet, or when no candidate splits the lines consistently
+    pub delimiter: Option<String>,
+    pub quoted: bool,
+    pub has_header: bool,
+    // JSON that is not one document per line: "documents" or "array"
+    pub json_records: Option<&'static str>,
+}
+
+impl Sniffed {
+    // One line summary for the report header, given the settings the input is read with
+    // (which the command line may have set in place of the guesses).
+    pub fn describe(&self, format: &str, delimiter: &str, has_header: bool) -> String {
+        match format {
+            "json" => format!("format json ({})", self.json_records.map_or("JSON Lines", |records| records)),
+            "parquet" => "format parquet".to_string(),
+            format => format!(
+                "format {}, {}{}, {}",
+                format,
+                match self.delimiter {
+                    Some(_) => format!("delimiter '{}'", delimiter.escape_debug()),
+                    None => format!("delimiter not detected (using '{}')", delimiter.escape_debug()),
+                },
+                if self.quoted { ", quoted fields" } else { "" },
+                if has_header { "header row" } else { "no header row" },
+            ),
+        }
+    }
+}
+
+// Guesses the format, delimiter, quoting and header of an input from its first lines (ending
+// in `terminator`), after `skip_rows` lines of preamble. The sample is replayed in front of the rest of the input by
+// the returned reader, so stdin can be sniffed too. A known delimiter can be given to only
+// guess the rest.
+pub fn sniff(
+    mut reader: Box<dyn BufRead>,
+    skip_rows: usize,
+    terminator: &str,
+    delimiter: Option<&str>,
+) -> io::Result<(Sniffed, Box<dyn BufRead>)> {
+    let mut sample = Vec::new();
+    let mut line_count = 0;
+    let mut preamble_len = 0;
+    // set when the sample stops short of the end of the input
+    let mut truncated = true;
+    while line_count < skip_rows + SNIFF_LINES && sample.len() < preamble_len + SNIFF_BYTES {
+        if read_record(&mut reader, terminator.as_bytes(), &mut sample)? == 0 {
+            truncated = false;
+            break;
+        }
+        line_count += 1;
+        if line_count == skip_rows {
+            preamble_len = sample.len();
+        }
+    }
+    let sniffed = if sample.starts_with(b"PAR1") {
+        Sniffed {
+            format: "parquet",
+            delimiter: None,
+            quoted: false,
+            has_header: true,
+            json_records: None,
+        }
+    } else {
+        guess(&sample[preamble_len.min(sample.len())..], terminator, delimiter, truncated)
+    };
+    let replay: Box<dyn BufRead> = Box::new(BufReader::new(Cursor::new(sample).chain(reader)));
+    Ok((sniffed, replay))
+}
+
+fn guess(sample: &[u8], terminator: &str, delimiter: Option<&str>, truncated: bool) -> Sniffed {
+    let mut sniffed = Sniffed {
+        format: "tabular",
+        delimiter: delimiter.map(|delimiter| delimiter.to_string()),
+        quoted: false,
+        has_header: true,
+        json_records: None,
+    };
+    let text = String::from_utf8_lossy(sample);
+    let mut lines = text
+        .split(terminator)
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<&str>>();
+    // the last line may have been cut short by the sample size cap
+    if truncated && lines.len() > 1 {
+        lines.pop();
+    }
+    if lines.is_empty() {
+        return sniffed;
+    }
+
+    if lines[0].trim_start().starts_with(['{', '[']) {
+        let is_document = |line: &str| {
+            line.trim_start().starts_with(['{', '[']) && serde_json::from_str::<serde_json::Value>(line).is_ok()
+        };
+        // one document per line, give or take a few bad ones
+        if is_document(lines[0]) || lines.iter().filter(|line| is_document(line)).count() * 2 > lines.len() {
+            sniffed.format = "json";
+            return sniffed;
+        }
+        // pretty-printed or wrapped in an array rather than one document per line
+        if is_json(&text, truncated) {
+            sniffed.format = "json";
+            sniffed.json_records = Some(if lines[0].trim_start().starts_with('[') { "array" } else { "documents" });
+            return sniffed;
+        }
+    }
+
+    if sniffed.delimiter.is_none() {
+        sniffed.delimiter = choose_delimiter(&text, &lines, terminator, truncated);
+    }
+    if let Some(delimiter) = &sniffed.delimiter {
+        sniffed.quoted = lines.iter().any(|line| {
+            line.split(delimiter.as_str()).any(|field| {
+                let field = field.trim();
+                field.len() >= 2 && field.starts_with('"')
+            })
+        });
+        // the csv reader only supports single byte delimiters
+        if sniffed.quoted && delimiter.len() == 1 {
+            sniffed.format = "csv";
+        }
+        sniffed.has_header = looks_like_header(&split_rows(&text, delimiter, terminator, truncated));
+    }
+    sniffed
+}
+
+// Whether the sample is a run of JSON documents. The last one may stop short when the sample
+// does.
+fn is_json(text: &str, truncated: bool) -> bool {
+    for document in serde_json::Deserializer::from_str(text).into_iter::<serde_json::Value>() {
+        if let Err(e) = document {
+            return truncated && e.is_eof();
+        }
+    }
+    true
+}
+
+// The sample cut into rows of fields. Single byte delimiters are parsed as csv, so that
+// quoted fields may hold delimiters and line breaks; longer ones split each line, as the
+// tabular format does. A row cut short by the end of a truncated sample is left out.
+fn split_rows(text: &str, delimiter: &str, terminator: &str, truncated: bool) -> Vec<Vec<String>> {
+    let csv_terminator = match terminator.as_bytes() {
+        b"\n" => Some(csv::Terminator::CRLF),
+        [byte] => Some(csv::Terminator::Any(*byte)),
+        _ => None,
+    };
+    let mut rows = match (delimiter.as_bytes(), csv_terminator) {
+        ([byte], Some(csv_terminator)) => csv::ReaderBuilder::new()
+            .delimiter(*byte)
+            .terminator(csv_terminator)
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(text.as_bytes())
+            .into_records()
+            .map_while(Result::ok)
+            .map(|record| record.iter().map(|field| field.to_string()).collect())
+            .collect::<Vec<Vec<String>>>(),
+        _ => text
+            .split(terminator)
+            .map(|line| line.trim_end_matches('\r').split(delimiter).map(|field| field.to_string()).collect())
+            .collect(),
+    };
+    rows.retain(|row| row.iter().any(|field| !field.trim().is_empty()));
+    if truncated && rows.len() > 1 {
+        rows.pop();
+    }
+    rows
+}
+
+// Picks the candidate that occurs the same (non-zero) number of times on the most lines,
+// preferring more fields when the consistency is equal. A multi-character delimiter wins
+// over the characters it is made of when every one of those characters belongs to it.
+fn choose_delimiter(text: &str, lines: &[&str], terminator: &str, truncated: bool) -> Option<String> {
+    let mut best: Option<(&str, f64, usize)> = None;
+    let mut covered = Vec::new();
+
+    for candidate in CANDIDATES {
+        if covered.iter().any(|multi: &&str| multi.contains(candidate)) {
+            continue;
+        }
+        let rows = split_rows(text, candidate, terminator, truncated);
+        if rows.is_empty() {
+            continue;
+        }
+        let counts = rows.iter().map(|row| row.len() - 1).collect::<Vec<usize>>();
+        let distinct = counts.iter().copied().collect::<HashSet<usize>>();
+        let (mode, mode_lines) = distinct
+            .into_iter()
+            .map(|count| (count, counts.iter().filter(|c| **c == count).count()))
+            .max_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)))
+            .unwrap_or((0, 0));
+        let share = mode_lines as f64 / rows.len() as f64;
+        if mode == 0 || share < MIN_CONSISTENCY {
+            continue;
+        }
+
+        if candidate.len() > 1 {
+            let occurrences = counts.iter().sum::<usize>();
+            let owns_its_characters = candidate.chars().all(|c| {
+                let per_delimiter = candidate.matches(c).count();
+                lines.iter().map(|line| line.matches(c).count()).sum::<usize>() == occurrences * per_delimiter
+            });
+            if !owns_its_characters {
+                continue;
+            }
+            covered.push(candidate);
+        }
+
+        let better = match best {
+            None => true,
+            Some((_, best_share, best_mode)) => share > best_share || (share == best_share && mode > best_mode),
+        };
+        if better {
+            best = Some((candidate, share, mode));
+        }
+    }
+    best.map(|(delimiter, _, _)| delimiter.to_string())
+}
+
+// Numbers, dates and times: digits with separators only.
+fn is_numeric_like(value: &str) -> bool {
+    value.chars().any(|c| c.is_ascii_digit())
+        && value.chars().all(|c| c.is_ascii_digit() || "+-.,:/ ".contains(c))
+}
+
+// Compares the first row with the rows below it column by column, much as Python's
+// csv.Sniffer.has_header does. A value that does not fit its column - text above numbers, or
+// a mask none of the column's values have when most of them share one - makes the row a
+// header, and a number above numbers votes for it being data. Text above text tells nothing
+// either way, so the row is a header unless some column says otherwise.
+fn looks_like_header(rows: &[Vec<String>]) -> bool {
+    if rows.is_empty() {
+        return true;
+    }
+    let clean = |value: &String| value.trim().trim_matches('"').to_string();
+    let mut data_votes = 0;
+    for (idx, first) in rows[0].iter().map(clean).enumerate() {
+        let body = rows[1..]
+            .iter()
+            .filter_map(|row| row.get(idx).map(clean))
+            .filter(|value| !value.is_empty())
+            .collect::<Vec<String>>();
+        if first.is_empty() || body.is_empty() {
+            continue;
+        }
+
+        if body.iter().all(|value| is_numeric_like(value)) {
+            if !is_numeric_like(&first) {
+                return true;
+            }
+            data_votes += 1;
+            continue;
+        }
+        let mut masks = HashMap::new();
+        for value in &body {
+            *masks.entry(LowGrainUnicode.mask(value)).or_insert(0) += 1;
+        }
+        let most_common = masks.values().copied().max().unwrap_or(0);
+        if most_common as f64 >= HEADER_MASK_SHARE * body.len() as f64
+            && !masks.contains_key(&LowGrainUnicode.mask(&first))
+        {
+            return true;
+        }
+    }
+    data_votes == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guessed(sample: &str) -> Sniffed {
+        guess(sample.as_bytes(), "\n", None, false)
+    }
+
+    #[test]
+    fn headers_are_told_from_data_by_the_rows_below() {
+        assert!(guessed("name|city\nAlice|London\nBob|Leeds\n").has_header);
+        assert!(!guessed("1|SW1 1AA|12.50\n2|N1 9GU|3.99\n3|EC1A 1BB|7.00\n").has_header);
+        // a single row gives no evidence either way
+        assert!(guessed("id|postcode\n").has_header);
+    }
+
+    #[test]
+    fn quoted_line_breaks_do_not_hide_the_delimiter() {
+        let sniffed = guessed("id,note,amount\n1,\"first line\nsecond line\",12.50\n2,plain,3.99\n3,\"a, b\",7.00\n");
+        assert_eq!(sniffed.format, "csv");
+        assert_eq!(sniffed.delimiter.as_deref(), Some(","));
+        assert!(sniffed.has_header);
+    }
+
+    #[test]
+    fn undetected_delimiters_are_reported() {
+        let sniffed = guessed("just one column\nof free text, mostly\nwith | odd ; characters\n");
+        assert_eq!(sniffed.delimiter, None);
+        assert_eq!(
+            sniffed.describe("csv", ",", true),
+            "format csv, delimiter not detected (using ','), header row"
+        );
+    }
+
+    #[test]
+    fn numeric_names_are_a_header() {
+        let sniffed = guessed("region|2021|2022\nLondon|10|20\nLeeds|11|21\nYork|12|22\n");
+        assert_eq!(sniffed.delimiter.as_deref(), Some("|"));
+        assert!(sniffed.has_header);
+    }
+
+    #[test]
+    fn repeated_names_are_a_header() {
+        let sniffed = guessed("x|x|y\n1|2|abc\n3|4|def\n");
+        assert!(sniffed.has_header);
+    }
+
+    #[test]
+    fn empty_names_are_a_header() {
+        let sniffed = guessed("id,name,\n1,Alice,\n2,Bob,\n");
+        assert_eq!(sniffed.delimiter.as_deref(), Some(","));
+        assert!(sniffed.has_header);
+    }
+
+    #[test]
+    fn json_layouts_are_told_apart() {
+        let lines = guessed("{\"id\": 1}\n{\"id\": 2\n{\"id\": 3}\n{\"id\": 4}\n");
+        assert_eq!((lines.format, lines.json_records), ("json", None));
+
+        let documents = guessed("{\n  \"id\": 1\n}\n{\n  \"id\": 2\n}\n");
+        assert_eq!((documents.format, documents.json_records), ("json", Some("documents")));
+
+        // an array cut short by the end of the sample
+        let array = guess(b"[\n  {\"id\": 1},\n  {\"id\": 2},\n  {\"id\"", "\n", None, true);
+        assert_eq!((array.format, array.json_records), ("json", Some("array")));
+    }
+
+    #[test]
+    fn brackets_alone_do_not_make_json() {
+        let sniffed = guessed("[id]|name\n1|a\n2|b\n");
+        assert_eq!(sniffed.format, "tabular");
+        assert_eq!(sniffed.delimiter.as_deref(), Some("|"));
+        assert!(sniffed.has_header);
+
+        let sniffed = guessed("{\n  \"id\": 1\n}\n{ oops\n");
+        assert_eq!(sniffed.format, "tabular");
+    }
+}